@@ -0,0 +1,456 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use bitcoincore_rpc::bitcoin::block::Header;
+use bitcoincore_rpc::bitcoin::consensus::encode::deserialize;
+use bitcoincore_rpc::bitcoin::{Block, BlockHash, Transaction, Txid};
+use bitcoincore_rpc::jsonrpc::{self, error::RpcError};
+use bitcoincore_rpc::{Client, RpcApi};
+use log::warn;
+use lru::LruCache;
+use serde::Deserialize;
+
+use crate::{IndexerError, Result};
+
+/// Sources accepted by `--block-source`: the RPC node, or a file of raw
+/// blocks (`file:-` reads standard input).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlockSourceKind {
+    Rpc,
+    File(PathBuf),
+}
+
+impl FromStr for BlockSourceKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.split_once(':') {
+            _ if s == "rpc" => Ok(BlockSourceKind::Rpc),
+            Some(("file", path)) if !path.is_empty() => Ok(BlockSourceKind::File(PathBuf::from(path))),
+            _ => Err(format!("expected `rpc` or `file:<path>`, got `{}`", s)),
+        }
+    }
+}
+
+/// The chain queries the indexer makes, served either by the node or by
+/// `FileSource`. Errors are reported the way the node reports them, so
+/// callers can tell a missing transaction apart from a failed call.
+pub trait BlockSource: Send + Sync {
+    fn block_count(&self) -> bitcoincore_rpc::Result<u64>;
+    fn block_hash(&self, height: u64) -> bitcoincore_rpc::Result<BlockHash>;
+    fn block(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<Block>;
+    fn block_header(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<Header>;
+    fn block_height(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<u64>;
+    /// Looks up `txid`, in `block_hash` if given; the node needs the block
+    /// unless it runs with `-txindex`.
+    fn raw_transaction(&self, txid: &Txid, block_hash: Option<&BlockHash>) -> bitcoincore_rpc::Result<Transaction>;
+    /// `raw_transaction`, along with the block the transaction was confirmed
+    /// in when the source can tell.
+    fn located_transaction(
+        &self,
+        txid: &Txid,
+        block_hash: Option<&BlockHash>,
+    ) -> bitcoincore_rpc::Result<(Transaction, Option<TxLocation>)> {
+        Ok((self.raw_transaction(txid, block_hash)?, None))
+    }
+    /// Transactions in the node's mempool, for `--index-mempool`.
+    fn mempool(&self) -> bitcoincore_rpc::Result<Vec<Txid>> {
+        // RPC_METHOD_NOT_FOUND
+        Err(rpc_error(-32601, "This block source has no mempool"))
+    }
+}
+
+/// The block a transaction was confirmed in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TxLocation {
+    pub block_hash: BlockHash,
+    pub height: u64,
+    // The block's header time
+    pub time: u32,
+}
+
+impl BlockSource for Client {
+    fn block_count(&self) -> bitcoincore_rpc::Result<u64> {
+        self.get_block_count()
+    }
+
+    fn block_hash(&self, height: u64) -> bitcoincore_rpc::Result<BlockHash> {
+        self.get_block_hash(height)
+    }
+
+    fn block(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<Block> {
+        self.get_block(hash)
+    }
+
+    fn block_header(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<Header> {
+        self.get_block_header(hash)
+    }
+
+    fn block_height(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<u64> {
+        Ok(self.get_block_header_info(hash)?.height as u64)
+    }
+
+    fn raw_transaction(&self, txid: &Txid, block_hash: Option<&BlockHash>) -> bitcoincore_rpc::Result<Transaction> {
+        self.get_raw_transaction(txid, block_hash)
+    }
+
+    fn located_transaction(
+        &self,
+        txid: &Txid,
+        block_hash: Option<&BlockHash>,
+    ) -> bitcoincore_rpc::Result<(Transaction, Option<TxLocation>)> {
+        let (tx, confirmed_in) = verbose_transaction(self, txid, block_hash)?;
+        let location = match confirmed_in {
+            Some((block_hash, time)) => Some(TxLocation { block_hash, height: header_height(self, &block_hash)?, time }),
+            None => None,
+        };
+        Ok((tx, location))
+    }
+
+    fn mempool(&self) -> bitcoincore_rpc::Result<Vec<Txid>> {
+        self.get_raw_mempool()
+    }
+}
+
+/// The height of the block with `hash`, reading nothing else of the verbose
+/// `getblockheader` result.
+fn header_height(client: &Client, hash: &BlockHash) -> bitcoincore_rpc::Result<u64> {
+    #[derive(Deserialize)]
+    struct VerboseHeader {
+        height: u64,
+    }
+    Ok(client.call::<VerboseHeader>("getblockheader", &[serde_json::to_value(hash)?, true.into()])?.height)
+}
+
+/// `getrawtransaction` in verbose mode: the transaction, and the hash and
+/// time of the block it was confirmed in, unless it is in the mempool.
+fn verbose_transaction(
+    client: &Client,
+    txid: &Txid,
+    block_hash: Option<&BlockHash>,
+) -> bitcoincore_rpc::Result<(Transaction, Option<(BlockHash, u32)>)> {
+    // Only the fields needed are read, the decoded inputs and outputs of
+    // the verbose result vary between node versions
+    #[derive(Deserialize)]
+    struct Verbose {
+        hex: String,
+        // Left out for mempool transactions
+        blockhash: Option<BlockHash>,
+        blocktime: Option<u32>,
+    }
+
+    let mut args = vec![serde_json::to_value(txid)?, true.into()];
+    if let Some(block_hash) = block_hash {
+        args.push(serde_json::to_value(block_hash)?);
+    }
+    let verbose: Verbose = client.call("getrawtransaction", &args)?;
+    let bytes = hex::decode(&verbose.hex)
+        .map_err(|e| bitcoincore_rpc::Error::ReturnedError(format!("Invalid transaction hex: {}", e)))?;
+    let tx = deserialize(&bytes)?;
+    Ok((tx, verbose.blockhash.zip(verbose.blocktime)))
+}
+
+/// The node's client, rebuilt with `connect` when a call loses its
+/// connection, e.g. because bitcoind restarted mid-sync. The call is then
+/// retried up to `max_retries` times, waiting `delay` before the first retry
+/// and twice as long before each further one. Errors the node answers with
+/// are returned as they are. The heights of blocks that previous
+/// transactions were confirmed in are remembered, as many inputs spend
+/// outputs of the same few blocks.
+pub struct ReconnectingClient {
+    client: RwLock<Client>,
+    connect: Box<dyn Fn() -> Result<Client> + Send + Sync>,
+    max_retries: u32,
+    delay: Duration,
+    // A block's height never changes, even once it is reorged out
+    heights: Mutex<LruCache<BlockHash, u64>>,
+}
+
+/// How many block heights `ReconnectingClient` remembers.
+const BLOCK_HEIGHT_CACHE_SIZE: usize = 10_000;
+
+impl ReconnectingClient {
+    pub fn new(
+        client: Client,
+        connect: impl Fn() -> Result<Client> + Send + Sync + 'static,
+        max_retries: u32,
+        delay: Duration,
+    ) -> ReconnectingClient {
+        ReconnectingClient {
+            client: RwLock::new(client),
+            connect: Box::new(connect),
+            max_retries,
+            delay,
+            heights: Mutex::new(LruCache::new(NonZeroUsize::new(BLOCK_HEIGHT_CACHE_SIZE).expect("non-zero"))),
+        }
+    }
+
+    /// The height of a block a transaction was confirmed in, remembered.
+    fn confirmed_height(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<u64> {
+        if let Some(height) = self.heights.lock().unwrap().get(hash) {
+            return Ok(*height);
+        }
+        let height = self.call(|client| header_height(client, hash))?;
+        self.heights.lock().unwrap().put(*hash, height);
+        Ok(height)
+    }
+
+    fn call<T>(&self, call: impl Fn(&Client) -> bitcoincore_rpc::Result<T>) -> bitcoincore_rpc::Result<T> {
+        let mut attempt = 0;
+        loop {
+            let result = call(&self.client.read().unwrap());
+            match result {
+                Err(e) if attempt < self.max_retries && is_connection_error(&e) => {
+                    let wait = self.delay.saturating_mul(1 << attempt.min(16));
+                    attempt += 1;
+                    warn!("Lost the connection to the node: {}. Reconnecting in {:?} (retry {}/{})",
+                        e, wait, attempt, self.max_retries);
+                    std::thread::sleep(wait);
+                    match (self.connect)() {
+                        Ok(client) => *self.client.write().unwrap() = client,
+                        Err(e) => warn!("Failed to rebuild the RPC client: {}", e),
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl BlockSource for ReconnectingClient {
+    fn block_count(&self) -> bitcoincore_rpc::Result<u64> {
+        self.call(|client| client.get_block_count())
+    }
+
+    fn block_hash(&self, height: u64) -> bitcoincore_rpc::Result<BlockHash> {
+        self.call(|client| client.get_block_hash(height))
+    }
+
+    fn block(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<Block> {
+        self.call(|client| client.get_block(hash))
+    }
+
+    fn block_header(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<Header> {
+        self.call(|client| client.get_block_header(hash))
+    }
+
+    fn block_height(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<u64> {
+        self.call(|client| client.block_height(hash))
+    }
+
+    fn raw_transaction(&self, txid: &Txid, block_hash: Option<&BlockHash>) -> bitcoincore_rpc::Result<Transaction> {
+        self.call(|client| client.get_raw_transaction(txid, block_hash))
+    }
+
+    fn located_transaction(
+        &self,
+        txid: &Txid,
+        block_hash: Option<&BlockHash>,
+    ) -> bitcoincore_rpc::Result<(Transaction, Option<TxLocation>)> {
+        let (tx, confirmed_in) = self.call(|client| verbose_transaction(client, txid, block_hash))?;
+        let location = match confirmed_in {
+            Some((block_hash, time)) => Some(TxLocation { block_hash, height: self.confirmed_height(&block_hash)?, time }),
+            None => None,
+        };
+        Ok((tx, location))
+    }
+
+    fn mempool(&self) -> bitcoincore_rpc::Result<Vec<Txid>> {
+        self.call(|client| client.get_raw_mempool())
+    }
+}
+
+/// Whether `err` means the call never got an answer, rather than the node
+/// answering it with an error. A node that is back up but still loading its
+/// block index (RPC_IN_WARMUP) is waited for the same way.
+fn is_connection_error(err: &bitcoincore_rpc::Error) -> bool {
+    match err {
+        bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Transport(_)) => true,
+        bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(e)) => e.code == -28,
+        _ => false,
+    }
+}
+
+/// A fixed chain read from a file with one hex-serialized block per line, as
+/// printed by `getblock <hash> 0`. Blank lines and lines starting with `#` are
+/// ignored. The first block is at height 0 and every later one must build on
+/// the block before it. Every transaction is indexed, as with `-txindex`.
+pub struct FileSource {
+    blocks: Vec<Block>,
+    heights: HashMap<BlockHash, usize>,
+    // Position of each transaction as (height, index in block)
+    transactions: HashMap<Txid, (usize, usize)>,
+}
+
+impl FileSource {
+    /// Reads the blocks from `path`, or from standard input when it is `-`.
+    pub fn open(path: &Path) -> Result<FileSource> {
+        let mut contents = String::new();
+        let read = if path == Path::new("-") {
+            std::io::stdin().read_to_string(&mut contents).map(|_| ())
+        } else {
+            std::fs::read_to_string(path).map(|file| contents = file)
+        };
+        read.map_err(|e| IndexerError::Config(format!("Cannot read block file {}: {}", path.display(), e)))?;
+
+        FileSource::parse(&contents).map_err(|e| IndexerError::Config(format!("Invalid block file {}: {}", path.display(), e)))
+    }
+
+    fn parse(contents: &str) -> std::result::Result<FileSource, String> {
+        let mut source = FileSource { blocks: Vec::new(), heights: HashMap::new(), transactions: HashMap::new() };
+
+        let lines = contents.lines().enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        for (line_number, line) in lines {
+            let block: Block = hex::decode(line)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| deserialize(&bytes).map_err(|e| e.to_string()))
+                .map_err(|e| format!("line {}: {}", line_number, e))?;
+            if let Some(parent) = source.blocks.last() {
+                if block.header.prev_blockhash != parent.block_hash() {
+                    return Err(format!(
+                        "line {}: block {} does not build on the previous block {}",
+                        line_number, block.block_hash(), parent.block_hash()
+                    ));
+                }
+            }
+
+            let height = source.blocks.len();
+            source.heights.insert(block.block_hash(), height);
+            for (index, tx) in block.txdata.iter().enumerate() {
+                source.transactions.insert(tx.txid(), (height, index));
+            }
+            source.blocks.push(block);
+        }
+
+        if source.blocks.is_empty() {
+            return Err("no blocks".to_string());
+        }
+        Ok(source)
+    }
+
+    fn find(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<&Block> {
+        self.heights.get(hash)
+            .map(|&height| &self.blocks[height])
+            .ok_or_else(|| not_found("Block not found"))
+    }
+}
+
+impl BlockSource for FileSource {
+    fn block_count(&self) -> bitcoincore_rpc::Result<u64> {
+        Ok(self.blocks.len() as u64 - 1)
+    }
+
+    fn block_hash(&self, height: u64) -> bitcoincore_rpc::Result<BlockHash> {
+        self.blocks.get(height as usize)
+            .map(Block::block_hash)
+            // RPC_INVALID_PARAMETER, as returned by the node
+            .ok_or_else(|| rpc_error(-8, "Block height out of range"))
+    }
+
+    fn block(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<Block> {
+        self.find(hash).cloned()
+    }
+
+    fn block_header(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<Header> {
+        self.find(hash).map(|block| block.header)
+    }
+
+    fn block_height(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<u64> {
+        self.heights.get(hash).map(|&height| height as u64).ok_or_else(|| not_found("Block not found"))
+    }
+
+    fn raw_transaction(&self, txid: &Txid, block_hash: Option<&BlockHash>) -> bitcoincore_rpc::Result<Transaction> {
+        let &(height, index) = self.transactions.get(txid)
+            .filter(|(height, _)| match block_hash {
+                Some(hash) => self.heights.get(hash) == Some(height),
+                None => true,
+            })
+            .ok_or_else(|| not_found("No such mempool or blockchain transaction"))?;
+        Ok(self.blocks[height].txdata[index].clone())
+    }
+
+    fn located_transaction(
+        &self,
+        txid: &Txid,
+        block_hash: Option<&BlockHash>,
+    ) -> bitcoincore_rpc::Result<(Transaction, Option<TxLocation>)> {
+        let tx = self.raw_transaction(txid, block_hash)?;
+        let height = self.transactions[txid].0;
+        let block = &self.blocks[height];
+        Ok((tx, Some(TxLocation { block_hash: block.block_hash(), height: height as u64, time: block.header.time })))
+    }
+}
+
+// RPC_INVALID_ADDRESS_OR_KEY, as returned by the node
+fn not_found(message: &str) -> bitcoincore_rpc::Error {
+    rpc_error(-5, message)
+}
+
+/// The error the node answers a failed call with, so backends standing in for
+/// it can report lookups that found nothing the same way.
+pub fn rpc_error(code: i32, message: &str) -> bitcoincore_rpc::Error {
+    bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(RpcError { code, message: message.to_string(), data: None }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::blockdata::constants::genesis_block;
+    use bitcoincore_rpc::bitcoin::consensus::encode::serialize_hex;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::Network;
+
+    #[test]
+    fn parses_block_source_flags() {
+        assert_eq!("rpc".parse(), Ok(BlockSourceKind::Rpc));
+        assert_eq!("file:blocks.hex".parse(), Ok(BlockSourceKind::File(PathBuf::from("blocks.hex"))));
+        assert_eq!("file:-".parse(), Ok(BlockSourceKind::File(PathBuf::from("-"))));
+        assert!("file:".parse::<BlockSourceKind>().is_err());
+        assert!("zmq".parse::<BlockSourceKind>().is_err());
+    }
+
+    #[test]
+    fn serves_blocks_and_transactions_like_the_node() {
+        let genesis = genesis_block(Network::Regtest);
+        let source = FileSource::parse(&format!("# regtest genesis\n\n{}\n", serialize_hex(&genesis))).unwrap();
+        let hash = genesis.block_hash();
+        let txid = genesis.txdata[0].txid();
+
+        assert_eq!(source.block_count().unwrap(), 0);
+        assert_eq!(source.block_hash(0).unwrap(), hash);
+        assert_eq!(source.block(&hash).unwrap(), genesis);
+        assert_eq!(source.raw_transaction(&txid, None).unwrap(), genesis.txdata[0]);
+        assert_eq!(source.raw_transaction(&txid, Some(&hash)).unwrap(), genesis.txdata[0]);
+        assert_eq!(
+            source.located_transaction(&txid, None).unwrap(),
+            (genesis.txdata[0].clone(), Some(TxLocation { block_hash: hash, height: 0, time: genesis.header.time }))
+        );
+
+        let missing = |result: bitcoincore_rpc::Result<Transaction>| matches!(
+            result,
+            Err(bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(e))) if e.code == -5
+        );
+        assert!(missing(source.raw_transaction(&Txid::all_zeros(), None)));
+        assert!(missing(source.raw_transaction(&txid, Some(&BlockHash::all_zeros()))));
+        assert!(source.block_hash(1).is_err());
+    }
+
+    #[test]
+    fn rejects_blocks_that_do_not_form_a_chain() {
+        let genesis = serialize_hex(&genesis_block(Network::Regtest));
+        let testnet = serialize_hex(&genesis_block(Network::Testnet));
+
+        let err = FileSource::parse(&format!("{}\n{}\n", genesis, testnet)).err().unwrap();
+
+        assert!(err.starts_with("line 2: "), "{}", err);
+        assert!(FileSource::parse("# nothing\n").is_err());
+        assert!(FileSource::parse("00").is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use bitcoincore_rpc::bitcoin::BlockHash;
+use serde::{Deserialize, Serialize};
+
+use crate::postgres::PostgresCheckpoints;
+use crate::{IndexerError, Result};
+
+/// Progress marker persisted between runs so a restart resumes where the
+/// previous process stopped instead of re-indexing from `start_height`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub height: i32,
+    pub hash: BlockHash,
+    /// `sequence` of the last delivered block update, 0 before the first.
+    /// Missing from checkpoints written before it was added.
+    #[serde(default)]
+    pub sequence: u64,
+}
+
+impl Checkpoint {
+    /// Reads a checkpoint from `path`, returning `None` if the file does not exist.
+    pub fn load(path: &Path) -> Result<Option<Checkpoint>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(IndexerError::Checkpoint(
+                    format!("Failed to read {}: {}", path.display(), e)
+                ))
+            }
+        };
+
+        serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|e| IndexerError::Checkpoint(
+                format!("Failed to parse {}: {}", path.display(), e)
+            ))
+    }
+
+    /// Writes the checkpoint to a temporary file next to `path` and renames it
+    /// into place, so a crash mid-write never leaves a truncated checkpoint.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp_path = temp_path(path);
+        let contents = serde_json::to_vec(self)
+            .map_err(|e| IndexerError::Checkpoint(format!("Failed to serialize checkpoint: {}", e)))?;
+
+        let write = || -> std::io::Result<()> {
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(&contents)?;
+            file.sync_all()?;
+            fs::rename(&tmp_path, path)
+        };
+
+        write().map_err(|e| IndexerError::Checkpoint(
+            format!("Failed to write {}: {}", path.display(), e)
+        ))
+    }
+}
+
+/// Where checkpoints are kept: a `--checkpoint-file`, or a row of the
+/// `--checkpoint-db` table.
+pub enum CheckpointStore {
+    File(PathBuf),
+    Postgres(Box<PostgresCheckpoints>),
+}
+
+impl CheckpointStore {
+    pub fn load(&self) -> Result<Option<Checkpoint>> {
+        match self {
+            CheckpointStore::File(path) => Checkpoint::load(path),
+            CheckpointStore::Postgres(db) => db.load(),
+        }
+    }
+
+    pub fn save(&self, checkpoint: &Checkpoint) -> Result<()> {
+        match self {
+            CheckpointStore::File(path) => checkpoint.save(path),
+            CheckpointStore::Postgres(db) => db.save(checkpoint),
+        }
+    }
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("network-indexer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn missing_checkpoint_loads_as_none() {
+        let dir = test_dir("missing-checkpoint");
+        assert_eq!(Checkpoint::load(&dir.join("checkpoint.json")).unwrap(), None);
+    }
+
+    #[test]
+    fn checkpoint_round_trips_without_leaving_temp_file() {
+        let dir = test_dir("round-trip-checkpoint");
+        let path = dir.join("checkpoint.json");
+        let checkpoint = Checkpoint {
+            height: 42,
+            hash: BlockHash::from_byte_array([7; 32]),
+            sequence: 3,
+        };
+
+        checkpoint.save(&path).unwrap();
+
+        assert_eq!(Checkpoint::load(&path).unwrap(), Some(checkpoint));
+        assert!(!temp_path(&path).exists());
+    }
+
+    #[test]
+    fn corrupt_checkpoint_is_an_error() {
+        let dir = test_dir("corrupt-checkpoint");
+        let path = dir.join("checkpoint.json");
+        fs::write(&path, "{\"height\": 4").unwrap();
+
+        assert!(matches!(Checkpoint::load(&path), Err(IndexerError::Checkpoint(_))));
+    }
+
+    #[test]
+    fn checkpoint_without_a_sequence_starts_it_at_zero() {
+        let dir = test_dir("sequence-checkpoint");
+        let path = dir.join("checkpoint.json");
+        fs::write(&path, format!("{{\"height\": 4, \"hash\": \"{}\"}}", BlockHash::from_byte_array([7; 32]))).unwrap();
+
+        assert_eq!(Checkpoint::load(&path).unwrap().map(|checkpoint| checkpoint.sequence), Some(0));
+    }
+}
@@ -0,0 +1,199 @@
+//! Support for `--config <file.toml>`. Keys mirror the `Args` field names;
+//! values given on the command line take precedence over the file, which
+//! takes precedence over the built-in defaults.
+
+use std::ffi::OsString;
+use std::path::Path;
+
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser};
+
+use crate::{Args, IndexerError, Result};
+
+/// Parses `argv`, then fills in anything not given on the command line from
+/// the `--config` file, if one was passed. Invalid command lines and `--help`
+/// exit the process as usual.
+pub fn parse_args(argv: Vec<OsString>) -> Result<Args> {
+    let matches = Args::command().get_matches_from(argv.clone());
+    let args = Args::from_arg_matches(&matches).map_err(|e| IndexerError::Config(e.to_string()))?;
+
+    let Some(path) = &args.config else {
+        return Ok(args);
+    };
+
+    let file_args = config_file_args(path, &matches)?;
+    let mut merged = Vec::with_capacity(argv.len() + file_args.len());
+    merged.extend(argv.first().cloned());
+    merged.extend(file_args);
+    merged.extend(argv.into_iter().skip(1));
+
+    Args::try_parse_from(merged).map_err(|e| IndexerError::Config(
+        format!("Invalid value in {}: {}", path.display(), e.render().to_string().trim())
+    ))
+}
+
+/// Translates the config file into command-line arguments, skipping keys
+/// that were already given on the command line.
+fn config_file_args(path: &Path, matches: &clap::ArgMatches) -> Result<Vec<OsString>> {
+    let contents = std::fs::read_to_string(path).map_err(|e| IndexerError::Config(
+        format!("Cannot read config file {}: {}", path.display(), e)
+    ))?;
+    let table: toml::Table = contents.parse().map_err(|e| IndexerError::Config(
+        format!("Cannot parse config file {}: {}", path.display(), e)
+    ))?;
+
+    let command = Args::command();
+    let mut file_args = Vec::new();
+
+    for (key, value) in table {
+        let arg = command.get_arguments()
+            .filter(|arg| !matches!(arg.get_id().as_str(), "config" | "help" | "version"))
+            .find(|arg| arg.get_id().as_str() == key)
+            .ok_or_else(|| IndexerError::Config(
+                format!("Unknown key `{}` in config file {}", key, path.display())
+            ))?;
+
+        if matches.value_source(&key) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+
+        let flag = format!("--{}", arg.get_long().unwrap_or(&key));
+        let invalid = |expected: &str| IndexerError::Config(
+            format!("Key `{}` in config file {} must be {}", key, path.display(), expected)
+        );
+
+        if !arg.get_action().takes_values() {
+            match value {
+                toml::Value::Boolean(true) => file_args.push(flag.into()),
+                toml::Value::Boolean(false) => {}
+                _ => return Err(invalid("a boolean")),
+            }
+            continue;
+        }
+
+        let values = match value {
+            toml::Value::Array(items) => items,
+            scalar => vec![scalar],
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::String(s) => s,
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Float(f) => f.to_string(),
+                toml::Value::Boolean(b) => b.to_string(),
+                _ => return Err(invalid("a string, number, boolean, or array of those")),
+            };
+            file_args.push(flag.clone().into());
+            file_args.push(value.into());
+        }
+    }
+
+    Ok(file_args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("network-indexer-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn argv(args: &[&str]) -> Vec<OsString> {
+        std::iter::once("network-indexer").chain(args.iter().copied()).map(OsString::from).collect()
+    }
+
+    #[test]
+    fn command_line_overrides_file_which_overrides_defaults() {
+        let path = write_config("precedence", r#"
+            rpc_host = "bitcoin"
+            rpc_port = 8332
+            network = "mainnet"
+            watch_addresses = ["bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"]
+        "#);
+
+        let args = parse_args(argv(&["--config", path.to_str().unwrap(), "--rpc-port", "18443"])).unwrap();
+
+        // Command line beats the file
+        assert_eq!(args.rpc_port, 18443);
+        // The file beats the defaults
+        assert_eq!(args.rpc_host, "bitcoin");
+        assert_eq!(args.network, crate::NetworkArg::Mainnet);
+        assert_eq!(args.watch_addresses, vec!["bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"]);
+        // Defaults fill in the rest
+        assert_eq!(args.poll_interval_secs, 10);
+        // Left unset so a cookie file can tell they were not given
+        assert_eq!(args.rpc_user, None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn batch_limit_and_poll_interval_default_to_previous_hardcoded_values() {
+        let args = parse_args(argv(&[])).unwrap();
+
+        assert_eq!(args.max_blocks_per_batch, 200);
+        assert_eq!(args.poll_interval_secs, 10);
+        assert!(Args::try_parse_from(argv(&["--max-blocks-per-batch", "0"])).is_err());
+        assert!(Args::try_parse_from(argv(&["--poll-interval-secs", "0"])).is_err());
+    }
+
+    #[test]
+    fn start_from_tip_conflicts_with_an_explicit_start_height() {
+        assert!(parse_args(argv(&["--start-from-tip"])).unwrap().start_from_tip);
+        assert!(Args::try_parse_from(argv(&["--start-from-tip", "--start-height", "5"])).is_err());
+    }
+
+    #[test]
+    fn reindex_subcommand_follows_file_settings() {
+        let path = write_config("reindex", "rpc_host = \"bitcoin\"\n");
+
+        let args = parse_args(argv(&["--config", path.to_str().unwrap(), "reindex", "--from", "5", "--to", "9"])).unwrap();
+
+        assert_eq!(args.command, Some(crate::Command::Reindex { from: 5, to: 9 }));
+        assert_eq!(args.rpc_host, "bitcoin");
+        assert_eq!(parse_args(argv(&[])).unwrap().command, None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn block_subcommand_takes_exactly_one_of_height_and_hash() {
+        let hash = "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206";
+
+        assert_eq!(parse_args(argv(&["block", "--height", "7"])).unwrap().command, Some(crate::Command::Block { height: Some(7), hash: None }));
+        assert_eq!(parse_args(argv(&["block", "--hash", hash])).unwrap().command, Some(crate::Command::Block { height: None, hash: Some(hash.parse().unwrap()) }));
+        assert!(Args::try_parse_from(argv(&["block"])).is_err());
+        assert!(Args::try_parse_from(argv(&["block", "--height", "7", "--hash", hash])).is_err());
+        assert!(Args::try_parse_from(argv(&["block", "--hash", "not-a-hash"])).is_err());
+    }
+
+    #[test]
+    fn require_txindex_defaults_on_and_can_be_turned_off_from_the_file() {
+        let path = write_config("require-txindex", "require_txindex = false\n");
+
+        assert!(parse_args(argv(&[])).unwrap().require_txindex);
+        assert!(!parse_args(argv(&["--config", path.to_str().unwrap()])).unwrap().require_txindex);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let path = write_config("unknown-key", "rpc_hots = \"bitcoin\"\n");
+
+        let err = parse_args(argv(&["--config", path.to_str().unwrap()])).unwrap_err();
+
+        assert!(err.to_string().contains("Unknown key `rpc_hots`"), "{}", err);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn type_mismatches_are_rejected() {
+        let path = write_config("type-mismatch", "rpc_port = \"not-a-port\"\n");
+
+        let err = parse_args(argv(&["--config", path.to_str().unwrap()])).unwrap_err();
+
+        assert!(err.to_string().contains("--rpc-port"), "{}", err);
+        std::fs::remove_file(path).unwrap();
+    }
+}
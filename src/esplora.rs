@@ -0,0 +1,273 @@
+use std::io::Read;
+use std::time::Duration;
+
+use bitcoincore_rpc::bitcoin::block::Header;
+use bitcoincore_rpc::bitcoin::consensus::encode::deserialize;
+use bitcoincore_rpc::bitcoin::{Block, BlockHash, Transaction, Txid};
+use bitcoincore_rpc::jsonrpc;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::block_source::{self, BlockSource, TxLocation};
+
+/// Reads the chain from an Esplora REST API, such as a self-hosted electrs or
+/// blockstream.info. Blocks, headers, and transactions are fetched in their
+/// consensus serialization and decoded as the node's are; JSON is only read
+/// for heights and where transactions were confirmed.
+pub struct EsploraSource {
+    base_url: String,
+    agent: ureq::Agent,
+}
+
+impl EsploraSource {
+    /// Requests taking longer than `timeout` in total fail with a transport error.
+    pub fn new(base_url: &str, timeout: Duration) -> EsploraSource {
+        EsploraSource {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+        }
+    }
+
+    /// GETs `path`, turning a 404 into the error the node gives for the same
+    /// lookup, with its RPC error `code`.
+    fn request(&self, path: &str, code: i32) -> bitcoincore_rpc::Result<ureq::Response> {
+        match self.agent.get(&format!("{}{}", self.base_url, path)).call() {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(404, response)) => {
+                let message = response.into_string().unwrap_or_default();
+                Err(block_source::rpc_error(code, message.trim()))
+            }
+            Err(e) => Err(transport_error(e)),
+        }
+    }
+
+    fn get(&self, path: &str, code: i32) -> bitcoincore_rpc::Result<String> {
+        Ok(self.request(path, code)?.into_string().map_err(transport_error)?.trim().to_string())
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, path: &str, code: i32) -> bitcoincore_rpc::Result<T> {
+        Ok(serde_json::from_str(&self.get(path, code)?)?)
+    }
+
+    /// GETs the binary body of `path`, e.g. `/block/:hash/raw`.
+    fn get_bytes(&self, path: &str, code: i32) -> bitcoincore_rpc::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.request(path, code)?.into_reader().read_to_end(&mut bytes).map_err(transport_error)?;
+        Ok(bytes)
+    }
+
+    /// GETs the hex body of `path`, e.g. `/tx/:txid/hex`, and decodes it.
+    fn get_hex<T: bitcoincore_rpc::bitcoin::consensus::Decodable>(&self, path: &str, code: i32) -> bitcoincore_rpc::Result<T> {
+        let bytes = hex::decode(self.get(path, code)?).map_err(|e| transport_error(std::io::Error::other(e)))?;
+        Ok(deserialize(&bytes)?)
+    }
+
+    fn transaction(&self, txid: &Txid) -> bitcoincore_rpc::Result<Transaction> {
+        // RPC_INVALID_ADDRESS_OR_KEY, "No such mempool or blockchain transaction"
+        let tx: Transaction = self.get_hex(&format!("/tx/{}/hex", txid), -5)?;
+        if tx.txid() != *txid {
+            return Err(transport_error(std::io::Error::other(format!("Esplora returned transaction {} for {}", tx.txid(), txid))));
+        }
+        Ok(tx)
+    }
+}
+
+fn transport_error(e: impl std::error::Error + Send + Sync + 'static) -> bitcoincore_rpc::Error {
+    bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Transport(Box::new(e)))
+}
+
+impl BlockSource for EsploraSource {
+    fn block_count(&self) -> bitcoincore_rpc::Result<u64> {
+        let height = self.get("/blocks/tip/height", -5)?;
+        height.parse().map_err(|_| transport_error(std::io::Error::other(format!("Invalid tip height `{}`", height))))
+    }
+
+    fn block_hash(&self, height: u64) -> bitcoincore_rpc::Result<BlockHash> {
+        // RPC_INVALID_PARAMETER, "Block height out of range"
+        let hash = self.get(&format!("/block-height/{}", height), -8)?;
+        Ok(serde_json::from_value(serde_json::Value::String(hash))?)
+    }
+
+    fn block(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<Block> {
+        // RPC_INVALID_ADDRESS_OR_KEY, "Block not found"
+        let block: Block = deserialize(&self.get_bytes(&format!("/block/{}/raw", hash), -5)?)?;
+        if block.block_hash() != *hash {
+            return Err(transport_error(std::io::Error::other(format!(
+                "Esplora returned block {} for {}", block.block_hash(), hash
+            ))));
+        }
+        Ok(block)
+    }
+
+    fn block_header(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<Header> {
+        self.get_hex(&format!("/block/{}/header", hash), -5)
+    }
+
+    fn block_height(&self, hash: &BlockHash) -> bitcoincore_rpc::Result<u64> {
+        Ok(self.get_json::<EsploraBlock>(&format!("/block/{}", hash), -5)?.height)
+    }
+
+    /// Esplora indexes every transaction, so the block hint is not needed.
+    fn raw_transaction(&self, txid: &Txid, _block_hash: Option<&BlockHash>) -> bitcoincore_rpc::Result<Transaction> {
+        self.transaction(txid)
+    }
+
+    fn located_transaction(
+        &self,
+        txid: &Txid,
+        _block_hash: Option<&BlockHash>,
+    ) -> bitcoincore_rpc::Result<(Transaction, Option<TxLocation>)> {
+        let tx = self.transaction(txid)?;
+        let status: EsploraStatus = self.get_json(&format!("/tx/{}/status", txid), -5)?;
+        Ok((tx, status.location()))
+    }
+
+    fn mempool(&self) -> bitcoincore_rpc::Result<Vec<Txid>> {
+        self.get_json("/mempool/txids", -5)
+    }
+}
+
+/// The field of Esplora's /block/:hash read here.
+#[derive(Deserialize)]
+struct EsploraBlock {
+    height: u64,
+}
+
+/// Where a transaction was confirmed, from /tx/:txid/status. The block
+/// fields are left out for mempool transactions.
+#[derive(Deserialize)]
+struct EsploraStatus {
+    block_height: Option<u64>,
+    block_hash: Option<BlockHash>,
+    block_time: Option<u32>,
+}
+
+impl EsploraStatus {
+    fn location(&self) -> Option<TxLocation> {
+        Some(TxLocation { block_hash: self.block_hash?, height: self.block_height?, time: self.block_time? })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::MockReceiver;
+    use bitcoincore_rpc::bitcoin::absolute::LockTime;
+    use bitcoincore_rpc::bitcoin::blockdata::constants::genesis_block;
+    use bitcoincore_rpc::bitcoin::consensus::encode::{serialize, serialize_hex};
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::{Network, OutPoint, ScriptBuf, Sequence, TxIn, TxOut, Witness};
+
+    const GENESIS_HASH: &str = "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206";
+
+    // /block/:hash of the regtest genesis block as Esplora serves it, extra fields included
+    const GENESIS_BLOCK: &str = r#"{"id":"0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206","height":0,"version":1,"timestamp":1296688602,"tx_count":1,"size":285,"weight":1140,"merkle_root":"4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b","previousblockhash":null,"mediantime":1296688602,"nonce":2,"bits":545259519,"difficulty":4.6565423739069247e-10}"#;
+
+    async fn esplora(responses: Vec<(u16, Vec<u8>)>) -> (MockReceiver, EsploraSource) {
+        let receiver = MockReceiver::with_byte_responses(responses).await;
+        // Drop the receiver's /hook path, Esplora paths are absolute
+        let base_url = receiver.url.trim_end_matches("/hook").to_string();
+        (receiver, EsploraSource::new(&base_url, Duration::from_secs(15)))
+    }
+
+    fn text(body: &str) -> (u16, Vec<u8>) {
+        (200, body.as_bytes().to_vec())
+    }
+
+    /// A made-up segwit spend of the genesis coinbase.
+    fn segwit_spend() -> Transaction {
+        let genesis = genesis_block(Network::Regtest);
+        Transaction {
+            version: 2,
+            lock_time: LockTime::from_consensus(101),
+            input: vec![TxIn {
+                previous_output: OutPoint::new(genesis.txdata[0].txid(), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence(0xffff_fffd),
+                witness: Witness::from_slice(&[vec![0x30; 71], vec![0x02; 33]]),
+            }],
+            output: vec![TxOut {
+                value: 4_999_990_000,
+                script_pubkey: ScriptBuf::from_bytes(hex::decode("0014751e76e8199196d454941c45d1b3a323f1433bd6").unwrap()),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn decodes_raw_blocks_and_headers() {
+        let genesis = genesis_block(Network::Regtest);
+        let (receiver, source) = esplora(vec![
+            text(GENESIS_HASH),
+            text("0"),
+            (200, serialize(&genesis)),
+            text(&serialize_hex(&genesis.header)),
+            text(GENESIS_BLOCK),
+            (200, serialize(&genesis)),
+        ]).await;
+
+        let (height, hash, block, header, block_height, wrong_block) = tokio::task::spawn_blocking(move || {
+            let hash = source.block_hash(0).unwrap();
+            (
+                source.block_count().unwrap(),
+                hash,
+                source.block(&hash).unwrap(),
+                source.block_header(&hash).unwrap(),
+                source.block_height(&hash).unwrap(),
+                source.block(&BlockHash::all_zeros()),
+            )
+        }).await.unwrap();
+
+        assert_eq!(height, 0);
+        assert_eq!(hash.to_string(), GENESIS_HASH);
+        assert_eq!(block, genesis);
+        assert_eq!(header, genesis.header);
+        assert_eq!(block_height, 0);
+        assert!(matches!(wrong_block, Err(bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Transport(_)))));
+        let paths: Vec<_> = receiver.requests().into_iter().map(|request| request.path).collect();
+        assert_eq!(paths, vec![
+            "/block-height/0".to_string(),
+            "/blocks/tip/height".to_string(),
+            format!("/block/{}/raw", GENESIS_HASH),
+            format!("/block/{}/header", GENESIS_HASH),
+            format!("/block/{}", GENESIS_HASH),
+            format!("/block/{}/raw", BlockHash::all_zeros()),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn looks_up_transactions_and_reports_missing_ones_like_the_node() {
+        let genesis = genesis_block(Network::Regtest);
+        let coinbase = genesis.txdata[0].clone();
+        let spend = segwit_spend();
+        let (receiver, source) = esplora(vec![
+            text(&serialize_hex(&spend)),
+            (404, b"Transaction not found".to_vec()),
+            text(&serialize_hex(&spend)),
+            text(r#"{"confirmed":false}"#),
+            text(&serialize_hex(&coinbase)),
+            text(&format!(r#"{{"confirmed":true,"block_height":0,"block_hash":"{}","block_time":1296688602}}"#, GENESIS_HASH)),
+            text(&serialize_hex(&spend)),
+        ]).await;
+
+        let (spend_txid, coinbase_txid) = (spend.txid(), coinbase.txid());
+        let (found, missing, unconfirmed, confirmed, wrong_tx) = tokio::task::spawn_blocking(move || (
+            source.raw_transaction(&spend_txid, None),
+            source.raw_transaction(&Txid::all_zeros(), None),
+            source.located_transaction(&spend_txid, None),
+            source.located_transaction(&coinbase_txid, None),
+            source.raw_transaction(&Txid::all_zeros(), None),
+        )).await.unwrap();
+
+        assert_eq!(found.unwrap(), spend);
+        assert!(matches!(
+            missing,
+            Err(bitcoincore_rpc::Error::JsonRpc(jsonrpc::Error::Rpc(e))) if e.code == -5 && e.message == "Transaction not found"
+        ));
+        assert_eq!(unconfirmed.unwrap(), (spend, None));
+        let (tx, location) = confirmed.unwrap();
+        assert_eq!(tx, coinbase);
+        assert_eq!(location, Some(TxLocation { block_hash: genesis.block_hash(), height: 0, time: genesis.header.time }));
+        assert!(wrong_tx.is_err());
+        assert_eq!(receiver.requests()[3].path, format!("/tx/{}/status", spend_txid));
+    }
+}
@@ -0,0 +1,396 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use log::{debug, info};
+use serde_json::Value;
+
+use crate::parquet::{self, ColumnType, ParquetFile};
+use crate::sink::Sink;
+use crate::{schema, IndexerError, Result, SinkFormat};
+
+const REORG_COLUMNS: [(&str, ColumnType); 4] = [
+    ("common_ancestor_height", ColumnType::Int64),
+    ("common_ancestor_hash", ColumnType::Utf8),
+    ("orphaned_height", ColumnType::Int64),
+    ("orphaned_hash", ColumnType::Utf8),
+];
+const BLOCK_COLUMNS: [(&str, ColumnType); 2] = [("indexed_height", ColumnType::Int64), ("indexed_block_hash", ColumnType::Utf8)];
+
+/// Writes the UTXO updates of every payload as CSV or Parquet rows to
+/// `--sink-dir`, one file per `rotate_blocks` heights, for loading into a
+/// warehouse without a live receiver. The file of the range being written
+/// ends in `.partial` until a later range is reached. Orphaned blocks are
+/// listed in a `reorgs` file rather than removed from the files holding
+/// their rows. Every payload is flushed, as one Parquet row group per file it
+/// touches, before it counts as delivered, so nothing is left buffered when
+/// the indexer stops.
+pub struct FileSink {
+    // Shared with the blocking pool, where the writes run
+    files: Arc<Files>,
+}
+
+struct Files {
+    dir: PathBuf,
+    format: SinkFormat,
+    rotate_blocks: u64,
+    // UtxoUpdate fields, in the order the schema lists them, typed as it describes them
+    columns: Vec<parquet::Column>,
+    current: Mutex<Option<RangeFile>>,
+}
+
+/// The open file of one block range.
+struct RangeFile {
+    first_height: u64,
+    table: Table,
+}
+
+/// An open CSV or Parquet file that rows are added to.
+enum Table {
+    Csv(BufWriter<File>),
+    // The rows pushed since the last row group
+    Parquet(ParquetFile, Vec<parquet::Row>),
+}
+
+impl FileSink {
+    pub fn new(dir: &Path, format: SinkFormat, rotate_blocks: u64) -> Result<FileSink> {
+        fs::create_dir_all(dir)
+            .map_err(|e| IndexerError::Config(format!("Cannot create --sink-dir {}: {}", dir.display(), e)))?;
+        let schema = schema::payload_schema();
+        let update = &schema["$defs"]["UtxoUpdate"];
+        let columns = update["required"]
+            .as_array()
+            .expect("UtxoUpdate lists its fields")
+            .iter()
+            .map(|column| {
+                let name = column.as_str().expect("field names are strings");
+                (name.to_string(), column_type(&schema, &update["properties"][name]))
+            })
+            .collect();
+        Ok(FileSink {
+            files: Arc::new(Files {
+                dir: dir.to_path_buf(),
+                format,
+                rotate_blocks,
+                columns,
+                current: Mutex::new(None),
+            }),
+        })
+    }
+}
+
+impl Files {
+    /// The completed file holding blocks `first_height` up to the next rotation.
+    fn range_path(&self, first_height: u64) -> PathBuf {
+        let last_height = first_height + self.rotate_blocks - 1;
+        self.dir.join(format!("utxos-{:010}-{:010}.{}", first_height, last_height, extension(self.format)))
+    }
+
+    fn partial_path(&self, first_height: u64) -> PathBuf {
+        self.range_path(first_height).with_extension(format!("{}.partial", extension(self.format)))
+    }
+
+    fn write(&self, payload: &[u8]) -> io::Result<()> {
+        let events = match serde_json::from_slice(payload)? {
+            Value::Array(events) => events,
+            event => vec![event],
+        };
+        let mut current = self.current.lock().unwrap();
+        for event in &events {
+            if event["type"] == "reorg" {
+                self.write_reorg(event)?;
+                continue;
+            }
+            let Some(height) = event["height"].as_u64() else {
+                debug!("File sink skipping {} event without a block", event["type"]);
+                continue;
+            };
+            let rows = utxo_updates(event);
+            if rows.is_empty() {
+                continue;
+            }
+            let file = self.range_file(&mut current, height)?;
+            for update in rows {
+                let fields = [&event["height"], &event["hash"]]
+                    .into_iter()
+                    .chain(self.columns.iter().map(|(column, _)| &update[column]));
+                file.table.push(fields)?;
+            }
+        }
+        match current.as_mut() {
+            Some(file) => file.table.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// The file of the range `height` falls in, finishing the current one
+    /// when it is another range.
+    fn range_file<'a>(&self, current: &'a mut Option<RangeFile>, height: u64) -> io::Result<&'a mut RangeFile> {
+        let first_height = height - height % self.rotate_blocks;
+        if current.as_ref().is_some_and(|file| file.first_height != first_height) {
+            self.finish(current.take().expect("checked above"))?;
+        }
+        if current.is_none() {
+            let completed = self.range_path(first_height);
+            let partial = self.partial_path(first_height);
+            // Blocks re-emitted after a reorg or `reindex` reopen a completed range
+            if completed.exists() {
+                fs::rename(&completed, &partial)?;
+            }
+            let header = BLOCK_COLUMNS.into_iter().chain(self.columns.iter().map(|(name, kind)| (name.as_str(), *kind)));
+            *current = Some(RangeFile { first_height, table: Table::open(self.format, &partial, header)? });
+        }
+        Ok(current.as_mut().expect("opened above"))
+    }
+
+    fn finish(&self, mut file: RangeFile) -> io::Result<()> {
+        file.table.flush()?;
+        let completed = self.range_path(file.first_height);
+        fs::rename(self.partial_path(file.first_height), &completed)?;
+        info!("Wrote {}", completed.display());
+        Ok(())
+    }
+
+    fn write_reorg(&self, reorg: &Value) -> io::Result<()> {
+        let path = self.dir.join(format!("reorgs.{}", extension(self.format)));
+        let mut table = Table::open(self.format, &path, REORG_COLUMNS)?;
+        for orphaned in reorg["orphaned_blocks"].as_array().into_iter().flatten() {
+            table.push([&reorg["common_ancestor_height"], &reorg["common_ancestor_hash"], &orphaned["height"], &orphaned["hash"]])?;
+        }
+        table.flush()
+    }
+}
+
+impl Table {
+    /// Opens `path` to add rows to, starting it with `header` if it is new.
+    /// Column types only apply to Parquet.
+    fn open<'a>(format: SinkFormat, path: &Path, header: impl IntoIterator<Item = (&'a str, ColumnType)>) -> io::Result<Table> {
+        match format {
+            SinkFormat::Csv => Ok(Table::Csv(open_csv(path, header.into_iter().map(|(name, _)| name))?)),
+            SinkFormat::Parquet => {
+                let columns = header.into_iter().map(|(name, kind)| (name.to_string(), kind)).collect();
+                Ok(Table::Parquet(ParquetFile::open(path, columns)?, Vec::new()))
+            }
+        }
+    }
+
+    fn push<'a>(&mut self, fields: impl IntoIterator<Item = &'a Value>) -> io::Result<()> {
+        match self {
+            Table::Csv(writer) => write_row(writer, fields.into_iter().map(csv_field)),
+            Table::Parquet(file, rows) => {
+                let row = file.columns().iter()
+                    .zip(fields)
+                    .map(|((name, kind), value)| parquet_value(*kind, value)
+                        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{} in {:?} column {}", value, kind, name))))
+                    .collect::<io::Result<_>>()?;
+                rows.push(row);
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes out the rows pushed so far.
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Table::Csv(writer) => writer.flush(),
+            Table::Parquet(file, rows) => {
+                file.write_row_group(rows)?;
+                rows.clear();
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Sink for FileSink {
+    fn publish<'a>(&'a self, payload: &'a [u8], idempotency_key: &'a str) -> BoxFuture<'a, Result<()>> {
+        let files = self.files.clone();
+        let payload = payload.to_vec();
+        Box::pin(async move {
+            let dir = files.dir.clone();
+            tokio::task::spawn_blocking(move || files.write(&payload))
+                .await
+                .map_err(|e| IndexerError::Worker(format!("File sink write failed: {}", e)))?
+                .map_err(|e| IndexerError::SinkFailed(format!("Writing {} to {}: {}", idempotency_key, dir.display(), e)))
+        })
+    }
+}
+
+fn extension(format: SinkFormat) -> &'static str {
+    match format {
+        SinkFormat::Csv => "csv",
+        SinkFormat::Parquet => "parquet",
+    }
+}
+
+/// The UTXO updates of a block event in either payload shape.
+fn utxo_updates(event: &Value) -> Vec<&Value> {
+    if let Some(updates) = event["utxo_updates"].as_array() {
+        return updates.iter().collect();
+    }
+    event["transactions"].as_array().into_iter().flatten()
+        .flat_map(|tx| tx["inputs"].as_array().into_iter().flatten().chain(tx["outputs"].as_array().into_iter().flatten()))
+        .collect()
+}
+
+/// Opens `path` for appending, writing `header` first if it is new.
+fn open_csv<'a>(path: &Path, header: impl IntoIterator<Item = &'a str>) -> io::Result<BufWriter<File>> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let empty = file.metadata()?.len() == 0;
+    let mut writer = BufWriter::new(file);
+    if empty {
+        write_row(&mut writer, header.into_iter().map(str::to_string))?;
+    }
+    Ok(writer)
+}
+
+fn write_row(writer: &mut impl Write, fields: impl IntoIterator<Item = String>) -> io::Result<()> {
+    let fields: Vec<String> = fields.into_iter().map(|field| csv_escape(&field)).collect();
+    writeln!(writer, "{}", fields.join(","))
+}
+
+/// Strings as they are, null as an empty field, and nested values such as
+/// witness stacks as compact JSON. Parquet keeps nulls as nulls.
+fn csv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// `value` as a Parquet value of type `kind`, or `None` if it does not fit.
+/// Integers sent as decimal strings, like amounts with --amounts-as-strings,
+/// are written as INT64 all the same; text columns hold what CSV would.
+fn parquet_value(kind: ColumnType, value: &Value) -> Option<Option<parquet::Value>> {
+    if value.is_null() {
+        return Some(None);
+    }
+    let value = match kind {
+        ColumnType::Int64 => parquet::Value::Int64(value.as_i64().or_else(|| value.as_str()?.parse().ok())?),
+        ColumnType::Boolean => parquet::Value::Boolean(value.as_bool()?),
+        ColumnType::Utf8 => parquet::Value::Utf8(csv_field(value)),
+    };
+    Some(Some(value))
+}
+
+/// The Parquet type of a field the payload schema describes with `property`:
+/// INT64 for integers, amounts included, BOOLEAN for booleans, and UTF-8 for
+/// anything else.
+fn column_type(schema: &Value, property: &Value) -> ColumnType {
+    let property = match property["$ref"].as_str().and_then(|path| path.strip_prefix("#/$defs/")) {
+        Some(name) => &schema["$defs"][name],
+        None => property,
+    };
+    let types: Vec<_> = match &property["type"] {
+        Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+        kind => kind.as_str().into_iter().collect(),
+    };
+    if types.contains(&"integer") {
+        ColumnType::Int64
+    } else if types.contains(&"boolean") {
+        ColumnType::Boolean
+    } else {
+        ColumnType::Utf8
+    }
+}
+
+/// Quotes fields holding separators, quotes, or line breaks (RFC 4180).
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(height: u64, hash: &str, ids: &[&str]) -> Vec<u8> {
+        // An amount as --amounts-as-strings sends it
+        let updates: Vec<_> = ids.iter().map(|id| serde_json::json!({"id": id, "address": "a,b", "vout": 1, "amount": "1000", "confirmed": true})).collect();
+        serde_json::to_vec(&serde_json::json!({"type": "block_update", "height": height, "hash": hash, "utxo_updates": updates})).unwrap()
+    }
+
+    #[tokio::test]
+    async fn lists_orphaned_blocks_and_reopens_their_range_for_the_replacements() {
+        let dir = std::env::temp_dir().join(format!("network-indexer-file-sink-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let sink = FileSink::new(&dir, SinkFormat::Csv, 10).unwrap();
+
+        sink.publish(&block(9, "aa", &["x:0"]), "block-9").await.unwrap();
+        sink.publish(&block(10, "bb", &["y:0"]), "block-10").await.unwrap();
+        let reorg = serde_json::json!([
+            {"type": "reorg", "reorg": true, "common_ancestor_height": 8, "common_ancestor_hash": "88",
+             "orphaned_blocks": [{"height": 9, "hash": "aa"}, {"height": 10, "hash": "bb"}]},
+            {"type": "block_update", "height": 9, "hash": "cc", "utxo_updates": [{"id": "z:0"}]},
+        ]);
+        sink.publish(&serde_json::to_vec(&reorg).unwrap(), "batch").await.unwrap();
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("reorgs.csv"), "common_ancestor_height,common_ancestor_hash,orphaned_height,orphaned_hash\n8,88,9,aa\n8,88,10,bb\n");
+        let first_range = read("utxos-0000000000-0000000009.csv.partial");
+        let rows: Vec<_> = first_range.lines().skip(1).map(|line| line.split(',').take(3).collect::<Vec<_>>().join(",")).collect();
+        assert_eq!(rows, vec!["9,aa,x:0", "9,cc,z:0"]);
+        assert!(first_range.contains(",\"a,b\","));
+        assert!(dir.join("utxos-0000000010-0000000019.csv").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn writes_the_same_rows_to_parquet_with_nulls_kept() {
+        let dir = std::env::temp_dir().join(format!("network-indexer-parquet-sink-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let sink = FileSink::new(&dir, SinkFormat::Parquet, 10).unwrap();
+
+        sink.publish(&block(9, "aa", &["x:0"]), "block-9").await.unwrap();
+        sink.publish(&block(10, "bb", &["y:0"]), "block-10").await.unwrap();
+        let reorg = serde_json::json!([
+            {"type": "reorg", "reorg": true, "common_ancestor_height": 8, "common_ancestor_hash": "88",
+             "orphaned_blocks": [{"height": 10, "hash": "bb"}]},
+            {"type": "block_update", "height": 10, "hash": "cc", "utxo_updates": [{"id": "z:0"}]},
+        ]);
+        sink.publish(&serde_json::to_vec(&reorg).unwrap(), "batch").await.unwrap();
+
+        let text = |value: &str| Some(parquet::Value::Utf8(value.to_string()));
+        let (columns, rows) = parquet::read_rows(&dir.join("utxos-0000000000-0000000009.parquet")).unwrap();
+        let column = |name: &str| columns.iter().position(|(column, _)| column == name).unwrap();
+        assert_eq!(columns[..4], [
+            ("indexed_height".to_string(), ColumnType::Int64),
+            ("indexed_block_hash".to_string(), ColumnType::Utf8),
+            ("id".to_string(), ColumnType::Utf8),
+            ("address".to_string(), ColumnType::Utf8),
+        ]);
+        for (name, kind) in [("vout", ColumnType::Int64), ("amount", ColumnType::Int64), ("confirmed", ColumnType::Boolean), ("spent_witness", ColumnType::Utf8)] {
+            assert_eq!(columns[column(name)].1, kind, "{}", name);
+        }
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][..4], [Some(parquet::Value::Int64(9)), text("aa"), text("x:0"), text("a,b")]);
+        assert_eq!(rows[0][column("amount")], Some(parquet::Value::Int64(1000)));
+        assert_eq!(rows[0][column("confirmed")], Some(parquet::Value::Boolean(true)));
+        assert_eq!(rows[0][column("spent_block")], None);
+
+        let (_, rows) = parquet::read_rows(&dir.join("utxos-0000000010-0000000019.parquet.partial")).unwrap();
+        let blocks: Vec<_> = rows.iter().map(|row| (row[1].clone(), row[2].clone())).collect();
+        assert_eq!(blocks, vec![(text("bb"), text("y:0")), (text("cc"), text("z:0"))]);
+        assert_eq!(rows[1][3], None);
+
+        let (columns, rows) = parquet::read_rows(&dir.join("reorgs.parquet")).unwrap();
+        assert_eq!(columns, REORG_COLUMNS.map(|(name, kind)| (name.to_string(), kind)));
+        assert_eq!(rows, vec![vec![Some(parquet::Value::Int64(8)), text("88"), Some(parquet::Value::Int64(10)), text("bb")]]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn quotes_only_fields_that_need_it() {
+        assert_eq!(csv_escape("bc1qxy2k"), "bc1qxy2k");
+        assert_eq!(csv_escape("a,b"), "\"a,b\"");
+        assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field(&serde_json::json!(["ab", "cd"])), "[\"ab\",\"cd\"]");
+        assert_eq!(csv_field(&Value::Null), "");
+    }
+}
@@ -0,0 +1,133 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde::Serialize;
+
+use crate::{http, Result};
+
+/// Liveness information updated by the indexer after every successful poll
+/// of the node and served on `/healthz`.
+pub struct Health {
+    staleness: Duration,
+    state: Mutex<HealthState>,
+}
+
+#[derive(Default)]
+struct HealthState {
+    last_poll: Option<Instant>,
+    last_processed_height: i32,
+    chain_tip: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    pub healthy: bool,
+    pub last_processed_height: i32,
+    pub chain_tip: i32,
+    pub blocks_behind: i32,
+    pub seconds_since_last_poll: Option<u64>,
+}
+
+impl Health {
+    /// The indexer is reported unhealthy once `staleness` passes without a
+    /// successful poll of the node.
+    pub fn new(staleness: Duration) -> Health {
+        Health {
+            staleness,
+            state: Mutex::new(HealthState::default()),
+        }
+    }
+
+    /// Records that the node answered a poll with `chain_tip`.
+    pub fn record_poll(&self, last_processed_height: i32, chain_tip: i32) {
+        let mut state = self.state.lock().unwrap();
+        state.last_poll = Some(Instant::now());
+        state.last_processed_height = last_processed_height;
+        state.chain_tip = chain_tip;
+    }
+
+    pub fn record_progress(&self, last_processed_height: i32) {
+        self.state.lock().unwrap().last_processed_height = last_processed_height;
+    }
+
+    pub fn report(&self) -> HealthReport {
+        let state = self.state.lock().unwrap();
+        let since_last_poll = state.last_poll.map(|last_poll| last_poll.elapsed());
+
+        HealthReport {
+            healthy: since_last_poll.is_some_and(|elapsed| elapsed <= self.staleness),
+            last_processed_height: state.last_processed_height,
+            chain_tip: state.chain_tip,
+            blocks_behind: (state.chain_tip - state.last_processed_height).max(0),
+            seconds_since_last_poll: since_last_poll.map(|elapsed| elapsed.as_secs()),
+        }
+    }
+}
+
+/// Binds `addr` and returns a future serving `GET /healthz` until it is dropped.
+pub fn serve(addr: SocketAddr, health: Arc<Health>) -> Result<impl Future<Output = ()>> {
+    http::serve(addr, "health", move |req| handle(req, &health))
+}
+
+fn handle(req: Request<Body>, health: &Health) -> Response<Body> {
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/healthz") => {
+            let report = health.report();
+            let status = if report.healthy {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            http::json_response(status, &report)
+        }
+        _ => http::not_found(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unhealthy_until_first_poll() {
+        let health = Health::new(Duration::from_secs(60));
+        assert!(!health.report().healthy);
+
+        health.record_poll(95, 100);
+
+        let report = health.report();
+        assert!(report.healthy);
+        assert_eq!(report.blocks_behind, 5);
+    }
+
+    #[test]
+    fn unhealthy_once_poll_is_stale() {
+        let health = Health::new(Duration::ZERO);
+        health.record_poll(100, 100);
+        std::thread::sleep(Duration::from_millis(5));
+
+        assert!(!health.report().healthy);
+    }
+
+    #[tokio::test]
+    async fn serves_status_codes_and_json() {
+        let health = Arc::new(Health::new(Duration::from_secs(60)));
+        let addr = http::free_local_addr();
+        tokio::spawn(serve(addr, health.clone()).unwrap());
+        let url = format!("http://{}/healthz", addr);
+
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        health.record_poll(90, 100);
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["last_processed_height"], 90);
+        assert_eq!(body["chain_tip"], 100);
+        assert_eq!(body["blocks_behind"], 10);
+    }
+}
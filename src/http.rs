@@ -0,0 +1,60 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+
+use crate::{IndexerError, Result};
+
+/// Binds `addr` and returns a future that answers every request with
+/// `handler` until it is dropped. `name` is only used in log and error
+/// messages.
+pub fn serve<F>(addr: SocketAddr, name: &'static str, handler: F) -> Result<impl Future<Output = ()>>
+where
+    F: Fn(Request<Body>) -> Response<Body> + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    let make_svc = make_service_fn(move |_| {
+        let handler = handler.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let handler = handler.clone();
+                async move { Ok::<_, Infallible>(handler(req)) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)
+        .map_err(|e| IndexerError::Server(format!("Failed to bind {} server to {}: {}", name, addr, e)))?
+        .serve(make_svc);
+
+    Ok(async move {
+        if let Err(e) = server.await {
+            log::error!("{} server stopped: {}", name, e);
+        }
+    })
+}
+
+pub fn not_found() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::empty())
+        .unwrap()
+}
+
+pub fn json_response(status: StatusCode, body: &impl serde::Serialize) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_vec(body).expect("serializable response")))
+        .unwrap()
+}
+
+/// Reserves a free local port for tests by binding and immediately releasing it.
+#[cfg(test)]
+pub fn free_local_addr() -> SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap()
+}
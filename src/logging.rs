@@ -0,0 +1,95 @@
+use std::io::Write;
+
+use chrono::Utc;
+use clap::ValueEnum;
+use log::kv::{Error as KvError, Key, Value, VisitSource};
+use log::Record;
+use serde_json::{Map, Value as JsonValue};
+
+/// Output formats accepted by `--log-format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+/// Log target of the per-call RPC traces.
+pub const RPC_TARGET: &str = "network_indexer::rpc";
+
+/// Installs the global logger. The level is still controlled by `RUST_LOG`,
+/// except that `verbose_rpc` turns on the RPC traces regardless.
+pub fn init(format: LogFormat, verbose_rpc: bool) {
+    let mut builder = env_logger::Builder::from_default_env();
+    if verbose_rpc {
+        builder.filter_module(RPC_TARGET, log::LevelFilter::Trace);
+    }
+    if format == LogFormat::Json {
+        builder.format(|buf, record| writeln!(buf, "{}", format_json(record)));
+    }
+    builder.init();
+}
+
+/// Renders a record as a single-line JSON object. Structured fields attached
+/// with `info!(height = 5; "...")` become top-level keys next to the standard
+/// `timestamp`, `level`, `target`, and `message`.
+fn format_json(record: &Record) -> String {
+    let mut fields = Map::new();
+    fields.insert("timestamp".to_string(), Utc::now().to_rfc3339().into());
+    fields.insert("level".to_string(), record.level().as_str().into());
+    fields.insert("target".to_string(), record.target().into());
+    fields.insert("message".to_string(), record.args().to_string().into());
+
+    let mut visitor = JsonFields(&mut fields);
+    if let Err(e) = record.key_values().visit(&mut visitor) {
+        fields.insert("log_error".to_string(), e.to_string().into());
+    }
+
+    JsonValue::Object(fields).to_string()
+}
+
+struct JsonFields<'a>(&'a mut Map<String, JsonValue>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), KvError> {
+        let value = if let Some(v) = value.to_i64() {
+            v.into()
+        } else if let Some(v) = value.to_u64() {
+            v.into()
+        } else if let Some(v) = value.to_f64() {
+            v.into()
+        } else if let Some(v) = value.to_bool() {
+            v.into()
+        } else {
+            value.to_string().into()
+        };
+        self.0.insert(key.as_str().to_string(), value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_lines_carry_standard_and_structured_fields() {
+        let kvs: [(&str, Value); 2] = [("height", Value::from(812_345)), ("hash", Value::from("00ab"))];
+        let line = format_json(
+            &Record::builder()
+                .args(format_args!("Processed block {}", 812_345))
+                .level(log::Level::Info)
+                .target("network_indexer")
+                .key_values(&kvs)
+                .build(),
+        );
+
+        let json: JsonValue = serde_json::from_str(&line).unwrap();
+        assert!(!line.contains('\n'));
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["target"], "network_indexer");
+        assert_eq!(json["message"], "Processed block 812345");
+        assert_eq!(json["height"], 812_345);
+        assert_eq!(json["hash"], "00ab");
+        assert!(chrono::DateTime::parse_from_rfc3339(json["timestamp"].as_str().unwrap()).is_ok());
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashSet;
+
+use bitcoincore_rpc::bitcoin::Txid;
+
+/// The mempool transactions announced with `--index-mempool` that have not
+/// been mined yet, so each is announced once and those that leave the
+/// mempool unmined can be reported as evicted.
+#[derive(Default)]
+pub struct MempoolTracker {
+    announced: HashSet<Txid>,
+}
+
+impl MempoolTracker {
+    /// Transactions of `mempool` not announced yet, in its order.
+    pub fn unannounced(&self, mempool: &[Txid]) -> Vec<Txid> {
+        mempool.iter().filter(|txid| !self.announced.contains(*txid)).copied().collect()
+    }
+
+    pub fn announce(&mut self, txids: impl IntoIterator<Item = Txid>) {
+        self.announced.extend(txids);
+    }
+
+    /// Announced transactions missing from `mempool`. Mined ones must have
+    /// been forgotten first, or they are taken for evicted.
+    pub fn evicted(&self, mempool: &[Txid]) -> Vec<Txid> {
+        let mempool: HashSet<&Txid> = mempool.iter().collect();
+        let mut evicted: Vec<Txid> = self.announced.iter().filter(|txid| !mempool.contains(txid)).copied().collect();
+        evicted.sort();
+        evicted
+    }
+
+    /// Stops tracking `txids`, once mined or reported as evicted.
+    pub fn forget<'a>(&mut self, txids: impl IntoIterator<Item = &'a Txid>) {
+        for txid in txids {
+            self.announced.remove(txid);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+    #[test]
+    fn announces_once_and_reports_only_unmined_departures_as_evicted() {
+        let [a, b, c] = [1, 2, 3].map(|byte| Txid::from_byte_array([byte; 32]));
+        let mut tracker = MempoolTracker::default();
+
+        assert_eq!(tracker.unannounced(&[a, b]), vec![a, b]);
+        tracker.announce([a, b]);
+        assert_eq!(tracker.unannounced(&[a, b, c]), vec![c]);
+        tracker.announce([c]);
+
+        // `a` was mined and `b` dropped
+        tracker.forget(&[a]);
+        assert_eq!(tracker.evicted(&[c]), vec![b]);
+        tracker.forget(&[b]);
+        assert!(tracker.evicted(&[c]).is_empty());
+        // A mined transaction returning to the mempool after a reorg is announced again
+        assert_eq!(tracker.unannounced(&[a, c]), vec![a]);
+    }
+}
@@ -0,0 +1,128 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::{Body, Method, Request, Response};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntGauge, Opts, Registry, TextEncoder,
+};
+
+use crate::{http, Result};
+
+/// Prometheus collectors for sync progress and throughput.
+pub struct Metrics {
+    registry: Registry,
+    pub processed_height: IntGauge,
+    pub chain_tip_height: IntGauge,
+    pub blocks_behind: IntGauge,
+    pub utxos_processed: IntCounter,
+    pub utxo_set_delta: IntGauge,
+    pub webhook_success: IntCounter,
+    pub webhook_failure: IntCounter,
+    pub prevtx_cache_hits: IntCounter,
+    pub prevtx_cache_misses: IntCounter,
+    pub rpc_latency: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        let registry = Registry::new_custom(Some("indexer".to_string()), None)
+            .expect("valid metrics namespace");
+
+        let metrics = Metrics {
+            processed_height: IntGauge::new("processed_height", "Height of the last delivered block").unwrap(),
+            chain_tip_height: IntGauge::new("chain_tip_height", "Block count reported by the node").unwrap(),
+            blocks_behind: IntGauge::new("blocks_behind", "Blocks between the chain tip and the last delivered block").unwrap(),
+            utxos_processed: IntCounter::new("utxos_processed_total", "UTXO updates produced").unwrap(),
+            utxo_set_delta: IntGauge::new("utxo_set_delta", "Spendable outputs created minus outputs spent since startup").unwrap(),
+            webhook_success: IntCounter::new("webhook_success_total", "Successful webhook deliveries").unwrap(),
+            webhook_failure: IntCounter::new("webhook_failure_total", "Webhook deliveries that failed after retries").unwrap(),
+            prevtx_cache_hits: IntCounter::new("prevtx_cache_hits_total", "Previous-output lookups served from the cache").unwrap(),
+            prevtx_cache_misses: IntCounter::new("prevtx_cache_misses_total", "Previous-output lookups fetched over RPC").unwrap(),
+            rpc_latency: HistogramVec::new(
+                HistogramOpts::from(Opts::new("rpc_call_duration_seconds", "Bitcoin RPC call latency")),
+                &["method"],
+            ).unwrap(),
+            registry,
+        };
+
+        metrics.registry.register(Box::new(metrics.processed_height.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.chain_tip_height.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.blocks_behind.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.utxos_processed.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.utxo_set_delta.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.webhook_success.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.webhook_failure.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.prevtx_cache_hits.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.prevtx_cache_misses.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.rpc_latency.clone())).unwrap();
+
+        metrics
+    }
+
+    /// Records the node's tip alongside our progress and the distance between them.
+    pub fn set_heights(&self, processed_height: i32, chain_tip_height: i32) {
+        self.processed_height.set(processed_height as i64);
+        self.chain_tip_height.set(chain_tip_height as i64);
+        self.blocks_behind.set((chain_tip_height - processed_height).max(0) as i64);
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut buf = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buf)
+            .expect("text encoding never fails");
+        String::from_utf8(buf).expect("text encoding is utf-8")
+    }
+}
+
+/// Binds `addr` and returns a future serving `GET /metrics` until it is dropped.
+pub fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> Result<impl Future<Output = ()>> {
+    http::serve(addr, "metrics", move |req| handle(req, &metrics))
+}
+
+fn handle(req: Request<Body>, metrics: &Metrics) -> Response<Body> {
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header("content-type", TextEncoder::new().format_type())
+            .body(Body::from(metrics.render()))
+            .unwrap(),
+        _ => http::not_found(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_behind_tracks_distance_to_tip() {
+        let metrics = Metrics::new();
+
+        metrics.set_heights(90, 100);
+
+        assert_eq!(metrics.blocks_behind.get(), 10);
+        let output = metrics.render();
+        assert!(output.contains("indexer_processed_height 90"));
+        assert!(output.contains("indexer_chain_tip_height 100"));
+        assert!(output.contains("indexer_blocks_behind 10"));
+    }
+
+    #[tokio::test]
+    async fn serves_metrics_over_http() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.utxos_processed.inc_by(3);
+        metrics.rpc_latency.with_label_values(&["getblock"]).observe(0.25);
+
+        let addr = http::free_local_addr();
+        tokio::spawn(serve(addr, metrics).unwrap());
+
+        let body = reqwest::get(format!("http://{}/metrics", addr)).await.unwrap().text().await.unwrap();
+        assert!(body.contains("indexer_utxos_processed_total 3"));
+        assert!(body.contains("indexer_rpc_call_duration_seconds_count{method=\"getblock\"} 1"));
+
+        let missing = reqwest::get(format!("http://{}/other", addr)).await.unwrap();
+        assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+}
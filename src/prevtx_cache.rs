@@ -0,0 +1,121 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bitcoincore_rpc::bitcoin::{Transaction, Txid};
+use lru::LruCache;
+
+use crate::block_source::TxLocation;
+
+/// A transaction referenced as a previous output, with the block it was
+/// confirmed in when the block source could tell.
+#[derive(Debug, PartialEq)]
+pub struct PrevTx {
+    pub tx: Transaction,
+    pub created_in: Option<TxLocation>,
+}
+
+/// Bounded LRU cache of transactions referenced as previous outputs, so
+/// inputs spending the same (or a recently seen) transaction don't each
+/// cost a `getrawtransaction` round-trip.
+pub struct PrevTxCache {
+    entries: Option<Mutex<LruCache<Txid, Arc<PrevTx>>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl PrevTxCache {
+    /// Creates a cache holding at most `capacity` transactions. A capacity of
+    /// zero disables caching entirely.
+    pub fn new(capacity: usize) -> PrevTxCache {
+        PrevTxCache {
+            entries: NonZeroUsize::new(capacity).map(|capacity| Mutex::new(LruCache::new(capacity))),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, txid: &Txid) -> Option<Arc<PrevTx>> {
+        let entry = self.entries.as_ref()
+            .and_then(|entries| entries.lock().unwrap().get(txid).cloned());
+
+        let counter = if entry.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        entry
+    }
+
+    pub fn insert(&self, txid: Txid, tx: Arc<PrevTx>) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap().put(txid, tx);
+        }
+    }
+
+    /// Drops every entry, e.g. after a reorg moved the transactions to other
+    /// blocks than the ones recorded.
+    pub fn clear(&self) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap().clear();
+        }
+    }
+
+    /// Returns the `(hits, misses)` counted since the cache was created.
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits.load(Ordering::Relaxed), self.misses.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoincore_rpc::bitcoin::absolute::LockTime;
+
+    fn transaction(version: i32) -> Arc<PrevTx> {
+        let tx = Transaction {
+            version,
+            lock_time: LockTime::ZERO,
+            input: Vec::new(),
+            output: Vec::new(),
+        };
+        Arc::new(PrevTx { tx, created_in: None })
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let cache = PrevTxCache::new(10);
+        let tx = transaction(1);
+        let txid = tx.tx.txid();
+
+        assert!(cache.get(&txid).is_none());
+        cache.insert(txid, tx.clone());
+        assert_eq!(cache.get(&txid), Some(tx));
+
+        assert_eq!(cache.stats(), (1, 1));
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entry_when_full() {
+        let cache = PrevTxCache::new(2);
+        let txs: Vec<_> = (1..=3).map(transaction).collect();
+
+        cache.insert(txs[0].tx.txid(), txs[0].clone());
+        cache.insert(txs[1].tx.txid(), txs[1].clone());
+        cache.get(&txs[0].tx.txid());
+        cache.insert(txs[2].tx.txid(), txs[2].clone());
+
+        assert!(cache.get(&txs[0].tx.txid()).is_some());
+        assert!(cache.get(&txs[1].tx.txid()).is_none());
+        assert!(cache.get(&txs[2].tx.txid()).is_some());
+    }
+
+    #[test]
+    fn zero_capacity_disables_caching() {
+        let cache = PrevTxCache::new(0);
+        let tx = transaction(1);
+
+        cache.insert(tx.tx.txid(), tx.clone());
+
+        assert!(cache.get(&tx.tx.txid()).is_none());
+        assert_eq!(cache.stats(), (0, 1));
+    }
+}
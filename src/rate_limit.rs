@@ -0,0 +1,112 @@
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Spaces calls to `wait` at least `1 / rate` seconds apart: a token bucket
+/// holding a single token. Waiting reserves the next free slot, so concurrent
+/// callers are let through one by one, and a caller that comes back after a
+/// long pause, e.g. when following the tip, goes through immediately.
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn per_second(rate: u32) -> RateLimiter {
+        RateLimiter {
+            interval: Duration::from_secs(1) / rate.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub async fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+}
+
+/// A counting semaphore for blocking code: at most `capacity` slots are held
+/// at a time, across every thread sharing it.
+pub struct Slots {
+    capacity: usize,
+    free: Mutex<usize>,
+    released: Condvar,
+}
+
+/// A slot held until dropped.
+pub struct Slot<'a>(&'a Slots);
+
+impl Slots {
+    pub fn new(capacity: usize) -> Slots {
+        let capacity = capacity.max(1);
+        Slots { capacity, free: Mutex::new(capacity), released: Condvar::new() }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Waits for a free slot and takes it.
+    pub fn acquire(&self) -> Slot<'_> {
+        let mut free = self.released.wait_while(self.free.lock().unwrap(), |free| *free == 0).unwrap();
+        *free -= 1;
+        Slot(self)
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        *self.0.free.lock().unwrap() += 1;
+        self.0.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn slots_are_held_by_at_most_capacity_threads() {
+        let slots = Slots::new(3);
+        let (held, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..5 {
+                        let _slot = slots.acquire();
+                        peak.fetch_max(held.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(1));
+                        held.fetch_sub(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+        assert_eq!(*slots.free.lock().unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn spaces_waits_by_the_rate_without_banking_idle_time() {
+        let limiter = RateLimiter::per_second(50);
+
+        let started = Instant::now();
+        for _ in 0..11 {
+            limiter.wait().await;
+        }
+        // The first call goes through at once, and the other ten 20ms apart
+        assert!(started.elapsed() >= Duration::from_millis(200), "took {:?}", started.elapsed());
+
+        // Idle time does not build up a burst
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let resumed = Instant::now();
+        limiter.wait().await;
+        limiter.wait().await;
+        assert!(resumed.elapsed() >= Duration::from_millis(20), "took {:?}", resumed.elapsed());
+    }
+}
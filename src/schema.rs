@@ -0,0 +1,279 @@
+use serde_json::{json, Value};
+
+/// JSON Schema (draft 2020-12) of what is posted to receivers, printed by the
+/// `schema` subcommand. Written by hand alongside the payload structs in
+/// `main.rs`; the tests validate real payloads against it and reject unknown
+/// fields, so a field added there fails them until it is described here.
+pub fn payload_schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "network-indexer webhook payload",
+        "description": "A single event, or an array of events when --webhook-batch-size is above 1.",
+        "oneOf": [
+            {"$ref": "#/$defs/Event"},
+            {"type": "array", "items": {"$ref": "#/$defs/Event"}}
+        ],
+        "$defs": {
+            "Event": event(),
+            "BlockUpdate": block_update(),
+            "BlockConnected": block_connected(),
+            "UtxoBatch": utxo_batch(),
+            "Reorg": reorg(),
+            "MempoolTransaction": mempool_transaction(),
+            "MempoolEviction": mempool_eviction(),
+            "UtxoUpdates": utxo_updates(),
+            "Transactions": transactions(),
+            "UtxoUpdate": utxo_update(),
+            "ScriptTypeCounts": script_type_counts(),
+            "CoinbaseInfo": coinbase_info(),
+            "Amount": amount(),
+            "Timestamp": timestamp()
+        }
+    })
+}
+
+fn event() -> Value {
+    json!({
+        "oneOf": [
+            {"$ref": "#/$defs/BlockUpdate"},
+            {"$ref": "#/$defs/BlockConnected"},
+            {"$ref": "#/$defs/UtxoBatch"},
+            {"$ref": "#/$defs/Reorg"},
+            {"$ref": "#/$defs/MempoolTransaction"},
+            {"$ref": "#/$defs/MempoolEviction"}
+        ]
+    })
+}
+
+fn block_update() -> Value {
+    json!({
+        "description": "A connected block with its UTXO updates (--webhook-events block_update).",
+        "type": "object",
+        "properties": {
+            "type": {"const": "block_update"},
+            "height": {"type": "integer"},
+            "hash": {"type": "string"},
+            "timestamp": {"$ref": "#/$defs/Timestamp"},
+            "total_input_value": {"$ref": "#/$defs/Amount", "description": "Satoshis spent by non-coinbase inputs"},
+            "total_output_value": {"$ref": "#/$defs/Amount", "description": "Satoshis created by non-coinbase outputs"},
+            "fees": {"$ref": "#/$defs/Amount", "description": "total_input_value - total_output_value"},
+            "skipped_transactions": {"type": "integer", "description": "Transactions left out for invalid data, always 0 with --strict"},
+            "size": {"type": "integer", "description": "Serialized size in bytes, witness data included"},
+            "weight": {"type": "integer", "description": "Weight units"},
+            "tx_count": {"type": "integer"},
+            "header_hex": {"type": ["string", "null"], "description": "Hex of the serialized 80-byte block header with --include-header"},
+            "script_type_counts": {"oneOf": [{"$ref": "#/$defs/ScriptTypeCounts"}, {"type": "null"}]},
+            "sequence": {"type": "integer", "minimum": 0, "description": "Delivery counter: 1 for the first block delivered, one up per block across restarts and reorgs; 0 from the reindex and block subcommands"},
+            "utxo_updates": {"$ref": "#/$defs/UtxoUpdates"},
+            "transactions": {"$ref": "#/$defs/Transactions"}
+        },
+        "required": [
+            "type", "height", "hash", "timestamp", "total_input_value", "total_output_value", "fees",
+            "skipped_transactions", "size", "weight", "tx_count", "header_hex", "script_type_counts", "sequence"
+        ],
+        "oneOf": [{"required": ["utxo_updates"]}, {"required": ["transactions"]}],
+        "additionalProperties": false
+    })
+}
+
+fn block_connected() -> Value {
+    json!({
+        "description": "Block metadata only (--webhook-events block_connected).",
+        "type": "object",
+        "properties": {
+            "type": {"const": "block_connected"},
+            "height": {"type": "integer"},
+            "hash": {"type": "string"},
+            "timestamp": {"$ref": "#/$defs/Timestamp"},
+            "utxo_count": {"type": "integer"}
+        },
+        "required": ["type", "height", "hash", "timestamp", "utxo_count"],
+        "additionalProperties": false
+    })
+}
+
+fn utxo_batch() -> Value {
+    json!({
+        "description": "A block's UTXO updates without its metadata (--webhook-events utxo_batch).",
+        "type": "object",
+        "properties": {
+            "type": {"const": "utxo_batch"},
+            "height": {"type": "integer"},
+            "hash": {"type": "string"},
+            "utxo_updates": {"$ref": "#/$defs/UtxoUpdates"},
+            "transactions": {"$ref": "#/$defs/Transactions"}
+        },
+        "required": ["type", "height", "hash"],
+        "oneOf": [{"required": ["utxo_updates"]}, {"required": ["transactions"]}],
+        "additionalProperties": false
+    })
+}
+
+fn reorg() -> Value {
+    json!({
+        "description": "Previously emitted blocks were orphaned: invalidate the UTXOs they created and restore those they spent.",
+        "type": "object",
+        "properties": {
+            "type": {"const": "reorg"},
+            "reorg": {"const": true},
+            "common_ancestor_height": {"type": "integer"},
+            "common_ancestor_hash": {"type": "string"},
+            "orphaned_blocks": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "height": {"type": "integer"},
+                        "hash": {"type": "string"}
+                    },
+                    "required": ["height", "hash"],
+                    "additionalProperties": false
+                }
+            }
+        },
+        "required": ["type", "reorg", "common_ancestor_height", "common_ancestor_hash", "orphaned_blocks"],
+        "additionalProperties": false
+    })
+}
+
+fn mempool_transaction() -> Value {
+    json!({
+        "description": "A transaction that entered the mempool, with unconfirmed UTXO updates (--index-mempool).",
+        "type": "object",
+        "properties": {
+            "type": {"const": "mempool_transaction"},
+            "txid": {"type": "string"},
+            "utxo_updates": {"$ref": "#/$defs/UtxoUpdates"},
+            "transactions": {"$ref": "#/$defs/Transactions"}
+        },
+        "required": ["type", "txid"],
+        "oneOf": [{"required": ["utxo_updates"]}, {"required": ["transactions"]}],
+        "additionalProperties": false
+    })
+}
+
+fn mempool_eviction() -> Value {
+    json!({
+        "description": "An announced mempool transaction left the mempool without being mined: drop its unconfirmed updates.",
+        "type": "object",
+        "properties": {
+            "type": {"const": "mempool_eviction"},
+            "txid": {"type": "string"}
+        },
+        "required": ["type", "txid"],
+        "additionalProperties": false
+    })
+}
+
+fn utxo_updates() -> Value {
+    json!({
+        "description": "With --payload-shape flat.",
+        "type": "array",
+        "items": {"$ref": "#/$defs/UtxoUpdate"}
+    })
+}
+
+fn transactions() -> Value {
+    json!({
+        "description": "With --payload-shape grouped, in the order the transactions appear in the block.",
+        "type": "array",
+        "items": {
+            "type": "object",
+            "properties": {
+                "txid": {"type": "string"},
+                "inputs": {"type": "array", "items": {"$ref": "#/$defs/UtxoUpdate"}, "description": "Spent records of the outputs the transaction spends"},
+                "outputs": {"type": "array", "items": {"$ref": "#/$defs/UtxoUpdate"}}
+            },
+            "required": ["txid", "inputs", "outputs"],
+            "additionalProperties": false
+        }
+    })
+}
+
+fn utxo_update() -> Value {
+    json!({
+        "description": "An output created in the block, or, with the spent_* fields set, an output it spends.",
+        "type": "object",
+        "properties": {
+            "id": {"type": "string", "description": "txid:vout, or txid:vout:height for the outputs of the two mainnet coinbases repeated before BIP30"},
+            "address": {"type": "string", "description": "Empty for outputs without an address; key hashes as P2WPKH addresses with --output-address-format bech32"},
+            "public_key": {"type": ["string", "null"], "description": "From the script of P2PK outputs, otherwise recovered from the spending input"},
+            "txid": {"type": "string"},
+            "vout": {"type": "integer"},
+            "amount": {"$ref": "#/$defs/Amount"},
+            "script_pub_key": {"type": "string", "description": "Hex locking script"},
+            "script_type": {"type": "string", "description": "P2PK, P2PKH, P2SH, P2WPKH, P2WSH, P2TR, ..."},
+            "address_type": {"type": ["string", "null"], "description": "p2pkh, p2sh, p2wpkh, p2wsh, p2tr or witness_unknown, null without an address"},
+            "op_return_data": {"type": ["string", "null"], "description": "Hex of the data pushed after OP_RETURN"},
+            "created_at": {"$ref": "#/$defs/Timestamp", "description": "Time of the block that created the output, or when an unconfirmed one was seen"},
+            "block_height": {"type": ["integer", "null"], "description": "Height of the block that created the output, null for unconfirmed outputs"},
+            "created_block_hash": {"type": ["string", "null"], "description": "Null on spent records whose creating block is unknown, which carry the spending block's height and time instead"},
+            "spent_txid": {"type": ["string", "null"]},
+            "spent_at": {"oneOf": [{"$ref": "#/$defs/Timestamp"}, {"type": "null"}]},
+            "spent_block": {"type": ["integer", "null"]},
+            "spent_block_hash": {"type": ["string", "null"]},
+            "spent_script_sig": {"type": ["string", "null"], "description": "Hex unlocking script of the spending input"},
+            "spent_witness": {"type": ["array", "null"], "items": {"type": "string"}, "description": "Hex witness stack items of the spending input"},
+            "redeem_script": {"type": ["string", "null"], "description": "Hex redeem script revealed by a P2SH spend"},
+            "witness_script": {"type": ["string", "null"], "description": "Hex witness script revealed by a P2WSH or P2SH-P2WSH spend"},
+            "spent_sequence": {"type": ["integer", "null"]},
+            "spent_lock_time": {"type": ["integer", "null"]},
+            "spent_signals_rbf": {"type": ["boolean", "null"]},
+            "confirmations": {"type": ["integer", "null"], "description": "Relative to the chain tip when the update was sent"},
+            "confirmed": {"type": "boolean", "description": "False on the updates of a mempool_transaction"},
+            "matures_at_height": {"type": ["integer", "null"], "description": "First height a coinbase output can be spent at"},
+            "coinbase_info": {"oneOf": [{"$ref": "#/$defs/CoinbaseInfo"}, {"type": "null"}]}
+        },
+        "required": [
+            "id", "address", "public_key", "txid", "vout", "amount", "script_pub_key", "script_type",
+            "address_type", "op_return_data", "created_at", "block_height", "created_block_hash",
+            "spent_txid", "spent_at", "spent_block", "spent_block_hash", "spent_script_sig", "spent_witness",
+            "redeem_script", "witness_script", "spent_sequence", "spent_lock_time", "spent_signals_rbf",
+            "confirmations", "confirmed", "matures_at_height", "coinbase_info"
+        ],
+        "additionalProperties": false
+    })
+}
+
+fn script_type_counts() -> Value {
+    let counts = json!({"type": "object", "additionalProperties": {"type": "integer"}});
+    json!({
+        "description": "Emitted UTXO updates of the block by script_type (--script-type-counts).",
+        "type": "object",
+        "properties": {
+            "created": counts,
+            "spent": counts
+        },
+        "required": ["created", "spent"],
+        "additionalProperties": false
+    })
+}
+
+fn coinbase_info() -> Value {
+    json!({
+        "description": "What the coinbase transaction claims, set on the outputs it creates.",
+        "type": "object",
+        "properties": {
+            "subsidy": {"$ref": "#/$defs/Amount", "description": "New coins allowed at the height by the halving schedule"},
+            "total_value": {"$ref": "#/$defs/Amount", "description": "All coinbase outputs: the subsidy plus the fees claimed"},
+            "witness_commitment_vout": {"type": ["integer", "null"], "description": "Output holding the BIP141 witness commitment"},
+            "witness_commitment": {"type": ["string", "null"], "description": "Hex of the committed 32-byte hash"}
+        },
+        "required": ["subsidy", "total_value", "witness_commitment_vout", "witness_commitment"],
+        "additionalProperties": false
+    })
+}
+
+fn amount() -> Value {
+    json!({
+        "description": "Satoshis; a decimal string instead of a number with --amounts-as-strings.",
+        "type": ["integer", "string"]
+    })
+}
+
+fn timestamp() -> Value {
+    json!({
+        "type": "string",
+        "format": "date-time"
+    })
+}
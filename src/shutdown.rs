@@ -0,0 +1,130 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+
+/// Cloneable flag used to ask the indexer to stop after the block it is
+/// currently processing.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl Shutdown {
+    pub fn trigger(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Blocks the current thread for `duration`, or until `trigger` is
+    /// called, for the waits of synchronous code. Returns whether it was.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        const CHECK_INTERVAL: Duration = Duration::from_millis(50);
+        let deadline = Instant::now() + duration;
+        loop {
+            if self.is_requested() {
+                return true;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            std::thread::sleep(remaining.min(CHECK_INTERVAL));
+        }
+    }
+
+    /// Completes once `trigger` has been called, immediately if it already was.
+    pub async fn wait(&self) {
+        let notified = self.notify.notified();
+        if self.is_requested() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+/// Completes when the process receives SIGINT (Ctrl-C) or SIGTERM.
+pub async fn wait_for_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            log::error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                log::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn wait_returns_immediately_once_triggered() {
+        let shutdown = Shutdown::default();
+        shutdown.trigger();
+
+        tokio::time::timeout(Duration::from_secs(1), shutdown.wait()).await.unwrap();
+        assert!(shutdown.is_requested());
+    }
+
+    #[tokio::test]
+    async fn trigger_wakes_pending_waiters() {
+        let shutdown = Shutdown::default();
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.wait().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!shutdown.is_requested());
+
+        shutdown.trigger();
+
+        tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn sleep_wakes_early_once_triggered() {
+        let shutdown = Shutdown::default();
+        assert!(!shutdown.sleep(Duration::from_millis(10)));
+
+        let trigger = std::thread::spawn({
+            let shutdown = shutdown.clone();
+            move || {
+                std::thread::sleep(Duration::from_millis(100));
+                shutdown.trigger();
+            }
+        });
+        let started = Instant::now();
+        assert!(shutdown.sleep(Duration::from_secs(30)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        trigger.join().unwrap();
+    }
+}
@@ -0,0 +1,32 @@
+use futures::future::BoxFuture;
+use log::info;
+
+use crate::webhook::Webhooks;
+use crate::Result;
+
+/// Where payloads are delivered: the webhook destinations, or a broker such
+/// as `RedisSink`. Payloads arrive serialized to JSON, so every sink posts
+/// the same bytes.
+pub trait Sink: Send + Sync {
+    /// Delivers one payload. `idempotency_key` only depends on the blocks in
+    /// it, so consumers can use it to discard duplicates.
+    fn publish<'a>(&'a self, payload: &'a [u8], idempotency_key: &'a str) -> BoxFuture<'a, Result<()>>;
+}
+
+impl Sink for Webhooks {
+    fn publish<'a>(&'a self, payload: &'a [u8], idempotency_key: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.send(payload, idempotency_key))
+    }
+}
+
+/// Stands in for the webhooks with `--no-webhook` or an empty `--webhook-url`:
+/// every payload is dropped after a summary line, so sync and classification
+/// can be checked from the logs and metrics alone.
+pub struct LogSink;
+
+impl Sink for LogSink {
+    fn publish<'a>(&'a self, payload: &'a [u8], idempotency_key: &'a str) -> BoxFuture<'a, Result<()>> {
+        info!("No webhook configured, dropping {} ({} bytes)", idempotency_key, payload.len());
+        Box::pin(async { Ok(()) })
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+use crate::{http, Result, UtxoUpdate};
+
+/// In-memory copy of every delivered `UtxoUpdate`, keyed by `txid:vout`, so
+/// the indexer can answer queries about the UTXOs it has seen since startup.
+#[derive(Default)]
+pub struct UtxoStore {
+    state: Mutex<StoreState>,
+}
+
+#[derive(Default)]
+struct StoreState {
+    utxos: HashMap<String, UtxoUpdate>,
+    by_address: HashMap<String, HashSet<String>>,
+}
+
+impl UtxoStore {
+    /// Records newly created outputs and marks spent ones. An output spent
+    /// before the store started is kept from its spent record alone.
+    pub fn ingest(&self, updates: &[UtxoUpdate]) {
+        let mut state = self.state.lock().unwrap();
+        for update in updates {
+            match state.utxos.get_mut(&update.id) {
+                Some(existing) if update.spent_txid.is_some() => {
+                    existing.spent_txid = update.spent_txid.clone();
+                    existing.spent_at = update.spent_at;
+                    existing.spent_block = update.spent_block;
+                    existing.spent_block_hash = update.spent_block_hash.clone();
+                    existing.spent_script_sig = update.spent_script_sig.clone();
+                    existing.spent_witness = update.spent_witness.clone();
+                    existing.redeem_script = update.redeem_script.clone();
+                    existing.witness_script = update.witness_script.clone();
+                    existing.spent_sequence = update.spent_sequence;
+                    existing.spent_lock_time = update.spent_lock_time;
+                    existing.spent_signals_rbf = update.spent_signals_rbf;
+                    if existing.public_key.is_none() {
+                        existing.public_key = update.public_key.clone();
+                    }
+                }
+                _ => {
+                    state.by_address.entry(update.address.clone()).or_default().insert(update.id.clone());
+                    state.utxos.insert(update.id.clone(), update.clone());
+                }
+            }
+        }
+    }
+
+    /// Undoes everything recorded above `height` after a reorg: outputs
+    /// created there are dropped and outputs spent there become unspent.
+    pub fn rewind(&self, height: i32) {
+        let mut state = self.state.lock().unwrap();
+        let StoreState { utxos, by_address } = &mut *state;
+
+        utxos.retain(|id, utxo| {
+            if utxo.block_height.is_some_and(|created| created > height) {
+                if let Some(ids) = by_address.get_mut(&utxo.address) {
+                    ids.remove(id);
+                }
+                return false;
+            }
+            if utxo.spent_block.is_some_and(|spent_block| spent_block > height) {
+                utxo.spent_txid = None;
+                utxo.spent_at = None;
+                utxo.spent_block = None;
+                utxo.spent_block_hash = None;
+                utxo.spent_script_sig = None;
+                utxo.spent_witness = None;
+                utxo.redeem_script = None;
+                utxo.witness_script = None;
+                utxo.spent_sequence = None;
+                utxo.spent_lock_time = None;
+                utxo.spent_signals_rbf = None;
+            }
+            true
+        });
+        by_address.retain(|_, ids| !ids.is_empty());
+    }
+
+    pub fn get(&self, id: &str) -> Option<UtxoUpdate> {
+        self.state.lock().unwrap().utxos.get(id).cloned()
+    }
+
+    /// Unspent outputs paying to `address`, ordered by height then id.
+    pub fn unspent_for(&self, address: &str) -> Vec<UtxoUpdate> {
+        let state = self.state.lock().unwrap();
+        let mut unspent: Vec<_> = state.by_address.get(address)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| state.utxos.get(id))
+            .filter(|utxo| utxo.spent_txid.is_none())
+            .cloned()
+            .collect();
+        unspent.sort_by(|a, b| (a.block_height, &a.id).cmp(&(b.block_height, &b.id)));
+        unspent
+    }
+}
+
+/// Binds `addr` and returns a future serving `GET /address/{addr}/utxos` and
+/// `GET /utxo/{txid}:{vout}` until it is dropped.
+pub fn serve(addr: SocketAddr, store: Arc<UtxoStore>) -> Result<impl Future<Output = ()>> {
+    http::serve(addr, "api", move |req| handle(req, &store))
+}
+
+fn handle(req: Request<Body>, store: &UtxoStore) -> Response<Body> {
+    if req.method() != Method::GET {
+        return http::not_found();
+    }
+
+    let path = req.uri().path();
+    if let Some(address) = path.strip_prefix("/address/").and_then(|rest| rest.strip_suffix("/utxos")) {
+        return http::json_response(StatusCode::OK, &store.unspent_for(address));
+    }
+    if let Some(id) = path.strip_prefix("/utxo/") {
+        return match store.get(id) {
+            Some(utxo) => http::json_response(StatusCode::OK, &utxo),
+            None => http::not_found(),
+        };
+    }
+    http::not_found()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    fn created(id: &str, address: &str, height: i32) -> UtxoUpdate {
+        let (txid, vout) = id.split_once(':').unwrap();
+        UtxoUpdate {
+            id: id.to_string(),
+            address: address.to_string(),
+            public_key: None,
+            txid: txid.to_string(),
+            vout: vout.parse().unwrap(),
+            amount: 1_000,
+            script_pub_key: String::new(),
+            script_type: "P2WPKH".to_string(),
+            address_type: Some("p2wpkh".to_string()),
+            op_return_data: None,
+            created_at: DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap(),
+            block_height: Some(height),
+            created_block_hash: None,
+            spent_txid: None,
+            spent_at: None,
+            spent_block: None,
+            spent_block_hash: None,
+            spent_script_sig: None,
+            spent_witness: None,
+            redeem_script: None,
+            witness_script: None,
+            spent_sequence: None,
+            spent_lock_time: None,
+            spent_signals_rbf: None,
+            confirmations: None,
+            confirmed: true,
+            matures_at_height: None,
+            coinbase_info: None,
+        }
+    }
+
+    fn spent(id: &str, address: &str, height: i32) -> UtxoUpdate {
+        UtxoUpdate {
+            spent_txid: Some("spender".to_string()),
+            spent_block: Some(height),
+            ..created(id, address, height)
+        }
+    }
+
+    #[test]
+    fn spends_remove_utxos_from_the_address_balance() {
+        let store = UtxoStore::default();
+
+        store.ingest(&[created("a:0", "alice", 1), created("a:1", "alice", 1), created("b:0", "bob", 1)]);
+        store.ingest(&[spent("a:0", "alice", 2)]);
+
+        let unspent: Vec<_> = store.unspent_for("alice").into_iter().map(|u| u.id).collect();
+        assert_eq!(unspent, vec!["a:1"]);
+        assert_eq!(store.get("a:0").unwrap().spent_block, Some(2));
+        assert!(store.unspent_for("carol").is_empty());
+    }
+
+    #[test]
+    fn rewind_undoes_blocks_above_the_common_ancestor() {
+        let store = UtxoStore::default();
+        store.ingest(&[created("a:0", "alice", 1)]);
+        store.ingest(&[spent("a:0", "alice", 2), created("c:0", "alice", 2)]);
+
+        store.rewind(1);
+
+        let unspent: Vec<_> = store.unspent_for("alice").into_iter().map(|u| u.id).collect();
+        assert_eq!(unspent, vec!["a:0"]);
+        assert!(store.get("c:0").is_none());
+    }
+
+    #[tokio::test]
+    async fn serves_address_and_outpoint_lookups() {
+        let store = Arc::new(UtxoStore::default());
+        store.ingest(&[created("a:0", "alice", 1)]);
+        let addr = http::free_local_addr();
+        tokio::spawn(serve(addr, store).unwrap());
+
+        let utxos: serde_json::Value = reqwest::get(format!("http://{}/address/alice/utxos", addr))
+            .await.unwrap().json().await.unwrap();
+        assert_eq!(utxos[0]["id"], "a:0");
+
+        let utxo: serde_json::Value = reqwest::get(format!("http://{}/utxo/a:0", addr))
+            .await.unwrap().json().await.unwrap();
+        assert_eq!(utxo["amount"], 1_000);
+
+        let missing = reqwest::get(format!("http://{}/utxo/z:9", addr)).await.unwrap();
+        assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,561 @@
+//! Helpers shared by the unit tests.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bitcoincore_rpc::{Auth, Client, RpcApi};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// A request captured by `MockReceiver`.
+#[derive(Debug, Clone)]
+pub struct ReceivedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl ReceivedRequest {
+    /// Returns the first header with the given (case-insensitive) name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).unwrap()
+    }
+}
+
+/// Minimal HTTP server standing in for a webhook receiver. Requests are
+/// recorded and answered from a queue of scripted `(status, body)` responses,
+/// falling back to `200 OK` once the queue is empty. A status of `0` closes
+/// the connection without answering, like a server going away mid-request.
+pub struct MockReceiver {
+    pub url: String,
+    requests: Arc<Mutex<Vec<ReceivedRequest>>>,
+    connections: Arc<Mutex<usize>>,
+}
+
+impl MockReceiver {
+    pub async fn start() -> MockReceiver {
+        MockReceiver::with_responses(Vec::new()).await
+    }
+
+    pub async fn with_statuses(statuses: Vec<u16>) -> MockReceiver {
+        MockReceiver::with_responses(statuses.into_iter().map(|status| (status, String::new())).collect()).await
+    }
+
+    pub async fn with_responses(responses: Vec<(u16, String)>) -> MockReceiver {
+        MockReceiver::with_byte_responses(responses.into_iter().map(|(status, body)| (status, body.into_bytes())).collect()).await
+    }
+
+    /// Like `with_responses`, for bodies that need not be text.
+    pub async fn with_byte_responses(responses: Vec<(u16, Vec<u8>)>) -> MockReceiver {
+        MockReceiver::spawn(responses, Duration::ZERO).await
+    }
+
+    /// A receiver that waits `delay` before answering each request with `200 OK`.
+    pub async fn slow(delay: Duration) -> MockReceiver {
+        MockReceiver::spawn(Vec::new(), delay).await
+    }
+
+    async fn spawn(responses: Vec<(u16, Vec<u8>)>, delay: Duration) -> MockReceiver {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let connections = Arc::new(Mutex::new(0));
+        let responses = Arc::new(Mutex::new(VecDeque::from(responses)));
+
+        let (recorded, accepted) = (requests.clone(), connections.clone());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                *accepted.lock().unwrap() += 1;
+                let recorded = recorded.clone();
+                let responses = responses.clone();
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    let mut chunk = [0u8; 4096];
+                    loop {
+                        let Some(request) = parse_request(&mut buf) else {
+                            match stream.read(&mut chunk).await {
+                                Ok(0) | Err(_) => return,
+                                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                            }
+                            continue;
+                        };
+                        recorded.lock().unwrap().push(request);
+                        tokio::time::sleep(delay).await;
+
+                        let (status, body) = responses.lock().unwrap().pop_front().unwrap_or((200, Vec::new()));
+                        if status == 0 {
+                            return;
+                        }
+                        let mut response = format!(
+                            "HTTP/1.1 {} Mock\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n",
+                            status,
+                            body.len()
+                        ).into_bytes();
+                        response.extend_from_slice(&body);
+                        if stream.write_all(&response).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+
+        MockReceiver { url, requests, connections }
+    }
+
+    pub fn requests(&self) -> Vec<ReceivedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Connections accepted so far.
+    pub fn connections(&self) -> usize {
+        *self.connections.lock().unwrap()
+    }
+
+    /// JSON bodies of every request received so far.
+    pub fn bodies(&self) -> Vec<serde_json::Value> {
+        self.requests().iter().map(ReceivedRequest::json).collect()
+    }
+}
+
+/// Parses one complete request off the front of `buf`, if one has arrived.
+fn parse_request(buf: &mut Vec<u8>) -> Option<ReceivedRequest> {
+    let header_end = buf.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    let content_length = headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let body_start = header_end + 4;
+    if buf.len() < body_start + content_length {
+        return None;
+    }
+
+    let body = buf[body_start..body_start + content_length].to_vec();
+    buf.drain(..body_start + content_length);
+
+    Some(ReceivedRequest { method, path, headers, body })
+}
+
+/// A throwaway regtest `bitcoind` with its own datadir and ports, started from
+/// the binary named by the `BITCOIND` environment variable. The node is
+/// stopped and its datadir removed on drop.
+pub struct RegtestNode {
+    pub rpc_port: u16,
+    pub rpc: Client,
+    process: Child,
+    datadir: PathBuf,
+}
+
+impl RegtestNode {
+    pub const RPC_USER: &'static str = "user";
+    pub const RPC_PASSWORD: &'static str = "password";
+
+    /// Starts a node and waits for its RPC to come up. Panics when `BITCOIND`
+    /// is not set, so tests using it are `#[ignore]`d by default.
+    pub fn start() -> RegtestNode {
+        let bitcoind = std::env::var_os("BITCOIND").expect("BITCOIND is not set");
+        let datadir = std::env::temp_dir().join(format!("network-indexer-regtest-{}-{}", std::process::id(), free_port()));
+        std::fs::create_dir_all(&datadir).unwrap();
+        let rpc_port = free_port();
+
+        let process = Command::new(bitcoind)
+            .arg("-regtest")
+            .arg(format!("-datadir={}", datadir.display()))
+            .arg(format!("-rpcport={}", rpc_port))
+            .arg(format!("-rpcuser={}", RegtestNode::RPC_USER))
+            .arg(format!("-rpcpassword={}", RegtestNode::RPC_PASSWORD))
+            .args(["-listen=0", "-txindex", "-fallbackfee=0.0001", "-printtoconsole=0"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("BITCOIND must point at a bitcoind binary");
+
+        let rpc = Client::new(
+            &format!("http://127.0.0.1:{}", rpc_port),
+            Auth::UserPass(RegtestNode::RPC_USER.to_string(), RegtestNode::RPC_PASSWORD.to_string()),
+        ).unwrap();
+        let node = RegtestNode { rpc_port, rpc, process, datadir };
+
+        // The RPC port opens before the node has finished loading
+        let started = Instant::now();
+        while let Err(e) = node.rpc.get_block_count() {
+            assert!(started.elapsed() < Duration::from_secs(30), "bitcoind did not start: {}", e);
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        node.rpc.create_wallet("default", None, None, None, None).unwrap();
+        node
+    }
+}
+
+impl Drop for RegtestNode {
+    fn drop(&mut self) {
+        let _ = self.rpc.stop();
+        let started = Instant::now();
+        while matches!(self.process.try_wait(), Ok(None)) && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = std::fs::remove_dir_all(&self.datadir);
+    }
+}
+
+/// A throwaway Postgres cluster in its own datadir, created with the `initdb`
+/// and `postgres` binaries in the directory named by the `POSTGRES_BIN`
+/// environment variable. Only accepts password (SCRAM) logins over TCP, with
+/// or without TLS under a self-signed `certificate` for `127.0.0.1`. The
+/// server is stopped and its datadir removed on drop.
+pub struct EphemeralPostgres {
+    pub url: String,
+    pub certificate: PathBuf,
+    process: Child,
+    datadir: PathBuf,
+}
+
+impl EphemeralPostgres {
+    pub const USER: &'static str = "indexer";
+    pub const PASSWORD: &'static str = "password";
+
+    /// Starts a server and waits for it to accept connections. Panics when
+    /// `POSTGRES_BIN` is not set, so tests using it are `#[ignore]`d by default.
+    pub fn start() -> EphemeralPostgres {
+        let bin = PathBuf::from(std::env::var_os("POSTGRES_BIN").expect("POSTGRES_BIN is not set"));
+        let datadir = std::env::temp_dir().join(format!("network-indexer-postgres-{}-{}", std::process::id(), free_port()));
+        let password_file = datadir.with_extension("password");
+        std::fs::write(&password_file, EphemeralPostgres::PASSWORD).unwrap();
+
+        let initdb = Command::new(bin.join("initdb"))
+            .arg("-D").arg(&datadir)
+            .args(["-U", EphemeralPostgres::USER, "--auth=scram-sha-256"])
+            .arg(format!("--pwfile={}", password_file.display()))
+            .stdout(Stdio::null())
+            .status()
+            .expect("POSTGRES_BIN must contain initdb");
+        let _ = std::fs::remove_file(&password_file);
+        assert!(initdb.success(), "initdb failed");
+        let certificate = datadir.join("server.crt");
+        write_self_signed_certificate(&certificate, &datadir.join("server.key"));
+
+        let port = free_port();
+        let process = Command::new(bin.join("postgres"))
+            .arg("-D").arg(&datadir)
+            .args(["-p", &port.to_string(), "-c", "listen_addresses=127.0.0.1", "-c", "ssl=on"])
+            .arg("-c").arg(format!("unix_socket_directories={}", datadir.display()))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("POSTGRES_BIN must contain postgres");
+        let url = format!(
+            "postgres://{}:{}@127.0.0.1:{}/postgres",
+            EphemeralPostgres::USER, EphemeralPostgres::PASSWORD, port
+        );
+        let postgres = EphemeralPostgres { url, certificate, process, datadir };
+
+        // The port opens before the server accepts logins
+        let started = Instant::now();
+        let ready = || Command::new(bin.join("pg_isready"))
+            .args(["-q", "-h", "127.0.0.1", "-p", &port.to_string()])
+            .status()
+            .is_ok_and(|status| status.success());
+        while !ready() {
+            assert!(started.elapsed() < Duration::from_secs(30), "postgres did not start");
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        postgres
+    }
+}
+
+impl Drop for EphemeralPostgres {
+    fn drop(&mut self) {
+        // SIGINT is a fast shutdown, which still releases shared memory
+        let _ = Command::new("kill").args(["-INT", &self.process.id().to_string()]).status();
+        let started = Instant::now();
+        while matches!(self.process.try_wait(), Ok(None)) && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = std::fs::remove_dir_all(&self.datadir);
+    }
+}
+
+/// Writes a self-signed certificate for `127.0.0.1` and `localhost` and its
+/// key, readable only by the owner as Postgres insists.
+fn write_self_signed_certificate(certificate: &Path, key: &Path) {
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::BigNum;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::extension::SubjectAlternativeName;
+    use openssl::x509::{X509Builder, X509NameBuilder};
+
+    let pkey = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "127.0.0.1").unwrap();
+    let name = name.build();
+
+    let mut builder = X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap()).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&pkey).unwrap();
+    builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+    let names = SubjectAlternativeName::new().ip("127.0.0.1").dns("localhost")
+        .build(&builder.x509v3_context(None, None)).unwrap();
+    builder.append_extension(names).unwrap();
+    builder.sign(&pkey, MessageDigest::sha256()).unwrap();
+
+    std::fs::write(certificate, builder.build().to_pem().unwrap()).unwrap();
+    std::fs::write(key, pkey.private_key_to_pem_pkcs8().unwrap()).unwrap();
+    std::fs::set_permissions(key, std::os::unix::fs::PermissionsExt::from_mode(0o600)).unwrap();
+}
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// Minimal Redis server standing in for a broker. Commands are recorded as
+/// their arguments and answered from a queue of scripted raw RESP replies;
+/// an empty reply closes the connection instead. Once the queue is empty,
+/// `XADD` gets an entry ID and anything else `+OK`.
+pub struct MockBroker {
+    pub url: String,
+    commands: Arc<Mutex<Vec<Vec<String>>>>,
+    connections: Arc<Mutex<usize>>,
+}
+
+impl MockBroker {
+    pub async fn start() -> MockBroker {
+        MockBroker::with_replies(Vec::new()).await
+    }
+
+    pub async fn with_replies(replies: Vec<&str>) -> MockBroker {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let connections = Arc::new(Mutex::new(0));
+        let replies = Arc::new(Mutex::new(replies.into_iter().map(str::to_string).collect::<VecDeque<_>>()));
+
+        let (recorded, accepted) = (commands.clone(), connections.clone());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                *accepted.lock().unwrap() += 1;
+                let recorded = recorded.clone();
+                let replies = replies.clone();
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    let mut chunk = [0u8; 4096];
+                    loop {
+                        let Some(command) = parse_command(&mut buf) else {
+                            match stream.read(&mut chunk).await {
+                                Ok(0) | Err(_) => return,
+                                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                            }
+                            continue;
+                        };
+                        let is_xadd = command[0].eq_ignore_ascii_case("XADD");
+                        let entries = {
+                            let mut recorded = recorded.lock().unwrap();
+                            recorded.push(command);
+                            recorded.len()
+                        };
+
+                        let reply = replies.lock().unwrap().pop_front().unwrap_or_else(|| match is_xadd {
+                            true => format!("${}\r\n{}-0\r\n", entries.to_string().len() + 2, entries),
+                            false => "+OK\r\n".to_string(),
+                        });
+                        if reply.is_empty() || stream.write_all(reply.as_bytes()).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+
+        MockBroker { url, commands, connections }
+    }
+
+    pub fn commands(&self) -> Vec<Vec<String>> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    /// Connections accepted so far.
+    pub fn connections(&self) -> usize {
+        *self.connections.lock().unwrap()
+    }
+}
+
+/// Parses one complete RESP array of bulk strings off the front of `buf`, if
+/// one has arrived.
+fn parse_command(buf: &mut Vec<u8>) -> Option<Vec<String>> {
+    fn line(buf: &[u8], at: usize) -> Option<(&str, usize)> {
+        let end = at + buf[at..].windows(2).position(|w| w == b"\r\n")?;
+        Some((std::str::from_utf8(&buf[at..end]).ok()?, end + 2))
+    }
+
+    let (count, mut at) = line(buf, 0)?;
+    let count: usize = count.strip_prefix('*')?.parse().ok()?;
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (len, start) = line(buf, at)?;
+        let len: usize = len.strip_prefix('$')?.parse().ok()?;
+        if buf.len() < start + len + 2 {
+            return None;
+        }
+        args.push(String::from_utf8_lossy(&buf[start..start + len]).to_string());
+        at = start + len + 2;
+    }
+    buf.drain(..at);
+    Some(args)
+}
+
+/// Minimal ZMTP 3.0 PUB socket standing in for bitcoind's ZMQ notifications,
+/// serving a single subscriber.
+pub struct MockZmqPublisher {
+    pub url: String,
+    listener: TcpListener,
+    subscriber: tokio::sync::Mutex<Option<TcpStream>>,
+}
+
+impl MockZmqPublisher {
+    pub async fn start() -> MockZmqPublisher {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("tcp://{}", listener.local_addr().unwrap());
+        MockZmqPublisher { url, listener, subscriber: tokio::sync::Mutex::new(None) }
+    }
+
+    /// Accepts the subscriber, completes the handshake, and returns the topic
+    /// it subscribed to.
+    pub async fn subscription(&self) -> Vec<u8> {
+        let (mut stream, _) = self.listener.accept().await.unwrap();
+        let mut greeting = [0u8; 64];
+        greeting[0] = 0xff;
+        greeting[9] = 0x7f;
+        greeting[10] = 3;
+        greeting[12..16].copy_from_slice(b"NULL");
+        stream.write_all(&greeting).await.unwrap();
+        stream.read_exact(&mut greeting).await.unwrap();
+
+        let ready = read_zmtp_frame(&mut stream).await;
+        assert!(ready.ends_with(b"Socket-Type\x00\x00\x00\x03SUB"), "unexpected READY {:?}", ready);
+        stream.write_all(b"\x04\x19\x05READY\x0bSocket-Type\x00\x00\x00\x03PUB").await.unwrap();
+
+        let subscription = read_zmtp_frame(&mut stream).await;
+        *self.subscriber.lock().await = Some(stream);
+        assert_eq!(subscription[0], 1, "not a subscription");
+        subscription[1..].to_vec()
+    }
+
+    /// Sends a multipart message to the subscriber.
+    pub async fn publish(&self, frames: &[&[u8]]) {
+        let mut subscriber = self.subscriber.lock().await;
+        let stream = subscriber.as_mut().expect("no subscriber yet");
+        for (i, frame) in frames.iter().enumerate() {
+            let more = u8::from(i + 1 < frames.len());
+            stream.write_all(&[more, frame.len() as u8]).await.unwrap();
+            stream.write_all(frame).await.unwrap();
+        }
+    }
+}
+
+/// Reads a short ZMTP frame and returns its body.
+async fn read_zmtp_frame(stream: &mut TcpStream) -> Vec<u8> {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header).await.unwrap();
+    let mut body = vec![0; header[1] as usize];
+    stream.read_exact(&mut body).await.unwrap();
+    body
+}
+
+/// Checks `value` against the subset of JSON Schema that `schema::payload_schema`
+/// uses: local `$ref`s, `type`, `const`, `properties`, `required`,
+/// `additionalProperties: false`, `items`, and `oneOf`. Returns the path of
+/// the first mismatch.
+pub fn validate_schema(root: &serde_json::Value, value: &serde_json::Value) -> std::result::Result<(), String> {
+    validate_at(root, root, value, "$")
+}
+
+fn validate_at(root: &serde_json::Value, schema: &serde_json::Value, value: &serde_json::Value, path: &str) -> std::result::Result<(), String> {
+    use serde_json::Value;
+
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.strip_prefix("#/$defs/").unwrap_or_else(|| panic!("unsupported $ref {}", reference));
+        validate_at(root, &root["$defs"][name], value, path)?;
+    }
+    if let Some(expected) = schema.get("const") {
+        if value != expected {
+            return Err(format!("{}: expected {}, got {}", path, expected, value));
+        }
+    }
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::String(kind) => vec![kind.as_str()],
+            kinds => kinds.as_array().unwrap().iter().map(|kind| kind.as_str().unwrap()).collect(),
+        };
+        let matches = |kind: &str| match kind {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            kind => panic!("unsupported type {}", kind),
+        };
+        if !types.iter().any(|kind| matches(kind)) {
+            return Err(format!("{}: expected {:?}, got {}", path, types, value));
+        }
+    }
+    if let Some(object) = value.as_object() {
+        for required in schema["required"].as_array().into_iter().flatten() {
+            if !object.contains_key(required.as_str().unwrap()) {
+                return Err(format!("{}: missing {}", path, required));
+            }
+        }
+        let properties = schema["properties"].as_object();
+        for (key, field) in object {
+            match properties.and_then(|properties| properties.get(key)) {
+                Some(property) => validate_at(root, property, field, &format!("{}.{}", path, key))?,
+                None if schema["additionalProperties"] == false => return Err(format!("{}: unexpected {}", path, key)),
+                None => {}
+            }
+        }
+    }
+    if let (Some(items), Some(schema)) = (value.as_array(), schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate_at(root, schema, item, &format!("{}[{}]", path, i))?;
+        }
+    }
+    if let Some(options) = schema["oneOf"].as_array() {
+        let errors: Vec<String> = options.iter()
+            .filter_map(|option| validate_at(root, option, value, path).err())
+            .collect();
+        if errors.len() != options.len() - 1 {
+            return Err(format!("{}: expected exactly one of {} alternatives to match: {:?}", path, options.len(), errors));
+        }
+    }
+    Ok(())
+}
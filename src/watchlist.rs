@@ -0,0 +1,376 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use bitcoincore_rpc::bitcoin::bip32::{ChildNumber, ExtendedPubKey};
+use bitcoincore_rpc::bitcoin::secp256k1::{Secp256k1, VerifyOnly};
+use bitcoincore_rpc::bitcoin::{base58, Network, PublicKey, ScriptBuf};
+use log::{debug, info};
+
+use crate::{address_script, extract_address, AddressFormat, IndexerError, Result};
+
+// BIP380 descriptor checksums
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const CHECKSUM_GENERATOR: [u64; 5] = [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd];
+
+/// SLIP-132 version bytes of bare extended keys, with the network and the
+/// script they imply. Descriptors name the script themselves.
+const KEY_VERSIONS: [([u8; 4], Network, ScriptKind); 6] = [
+    ([0x04, 0x88, 0xb2, 0x1e], Network::Bitcoin, ScriptKind::Pkh), // xpub
+    ([0x04, 0x9d, 0x7c, 0xb2], Network::Bitcoin, ScriptKind::ShWpkh), // ypub
+    ([0x04, 0xb2, 0x47, 0x46], Network::Bitcoin, ScriptKind::Wpkh), // zpub
+    ([0x04, 0x35, 0x87, 0xcf], Network::Testnet, ScriptKind::Pkh), // tpub
+    ([0x04, 0x4a, 0x52, 0x62], Network::Testnet, ScriptKind::ShWpkh), // upub
+    ([0x04, 0x5f, 0x1c, 0xf6], Network::Testnet, ScriptKind::Wpkh), // vpub
+];
+
+/// Addresses to emit UTXOs for: the `--watch-address` list, plus a window
+/// of addresses derived from each `--watch-descriptor`. The window reaches
+/// `gap_limit` addresses past the last one seen in a block and moves on as
+/// later ones are hit, as a wallet's address discovery does.
+pub struct Watchlist {
+    network: Network,
+    address_format: AddressFormat,
+    descriptors: Vec<WatchDescriptor>,
+    gap_limit: u32,
+    secp: Secp256k1<VerifyOnly>,
+    state: RwLock<WatchState>,
+}
+
+#[derive(Default)]
+struct WatchState {
+    // Derived addresses map to their descriptor and child index
+    addresses: HashMap<String, Option<(usize, u32)>>,
+    // Children derived so far, per descriptor
+    derived: Vec<u32>,
+}
+
+impl Watchlist {
+    pub fn new(
+        addresses: HashSet<String>,
+        descriptors: Vec<WatchDescriptor>,
+        gap_limit: u32,
+        network: Network,
+        address_format: AddressFormat,
+    ) -> Watchlist {
+        let state = WatchState {
+            addresses: addresses.into_iter().map(|address| (address, None)).collect(),
+            derived: vec![0; descriptors.len()],
+        };
+        let watchlist = Watchlist { network, address_format, descriptors, gap_limit, secp: Secp256k1::verification_only(), state: RwLock::new(state) };
+        {
+            let mut state = watchlist.state.write().unwrap();
+            for (index, descriptor) in watchlist.descriptors.iter().enumerate() {
+                let window = if descriptor.wildcard { gap_limit } else { 1 };
+                watchlist.derive_up_to(&mut state, index, window);
+                info!("Watching {} address(es) of descriptor {}", state.derived[index], descriptor.source);
+            }
+        }
+        watchlist
+    }
+
+    /// Whether UTXOs paying to `address` are emitted. A hit on a derived
+    /// address derives the next `gap_limit` addresses after it.
+    pub fn contains(&self, address: &str) -> bool {
+        let hit = match self.state.read().unwrap().addresses.get(address) {
+            None => return false,
+            Some(hit) => *hit,
+        };
+        if let Some((index, child)) = hit {
+            if self.descriptors[index].wildcard {
+                let mut state = self.state.write().unwrap();
+                self.derive_up_to(&mut state, index, child.saturating_add(1).saturating_add(self.gap_limit));
+            }
+        }
+        true
+    }
+
+    /// Whether a descriptor's window can still move on, which makes whether
+    /// a block's UTXOs are emitted depend on the blocks processed before it.
+    pub fn grows(&self) -> bool {
+        self.descriptors.iter().any(|descriptor| descriptor.wildcard)
+    }
+
+    /// Every address watched at the moment.
+    pub fn addresses(&self) -> HashSet<String> {
+        self.state.read().unwrap().addresses.keys().cloned().collect()
+    }
+
+    fn derive_up_to(&self, state: &mut WatchState, index: usize, count: u32) {
+        let descriptor = &self.descriptors[index];
+        while state.derived[index] < count {
+            let child = state.derived[index];
+            let script = descriptor.script_at(&self.secp, child);
+            if let Some(address) = extract_address(address_script(&script, self.address_format), self.network) {
+                state.addresses.entry(address).or_insert(Some((index, child)));
+            }
+            state.derived[index] += 1;
+        }
+        debug!("Derived {} address(es) of descriptor {}", state.derived[index], descriptor.source);
+    }
+}
+
+/// Script templates that single-key descriptors can wrap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ScriptKind {
+    Pkh,
+    Wpkh,
+    ShWpkh,
+    // Key path only, as BIP86
+    Tr,
+}
+
+/// A parsed `--watch-descriptor`: one of `pkh(KEY)`, `wpkh(KEY)`,
+/// `sh(wpkh(KEY))` or `tr(KEY)`, where KEY is an extended public key with an
+/// optional `[fingerprint/path]` origin, followed by unhardened steps and
+/// usually a final `/*`. A bare extended key watches its receive (`/0/*`)
+/// and change (`/1/*`) chains, as the script its SLIP-132 prefix names.
+pub struct WatchDescriptor {
+    // As given, for logs
+    source: String,
+    kind: ScriptKind,
+    key: ExtendedPubKey,
+    path: Vec<ChildNumber>,
+    wildcard: bool,
+}
+
+impl WatchDescriptor {
+    /// Parses `descriptor`, which may stand for several, as a bare key does.
+    pub fn parse(descriptor: &str, network: Network) -> Result<Vec<WatchDescriptor>> {
+        let invalid = |reason: String| IndexerError::Config(format!("Invalid watch descriptor {}: {}", descriptor, reason));
+        let descriptor = descriptor.trim();
+        let body = match descriptor.split_once('#') {
+            Some((body, checksum)) => {
+                let expected = descriptor_checksum(body).ok_or_else(|| invalid("unexpected character".to_string()))?;
+                if checksum != expected {
+                    return Err(invalid(format!("checksum {} does not match, expected {}", checksum, expected)));
+                }
+                body
+            }
+            None => descriptor,
+        };
+
+        let (kind, key) = if let Some(key) = body.strip_prefix("sh(wpkh(").and_then(|rest| rest.strip_suffix("))")) {
+            (Some(ScriptKind::ShWpkh), key)
+        } else if let Some(key) = body.strip_prefix("wpkh(").and_then(|rest| rest.strip_suffix(')')) {
+            (Some(ScriptKind::Wpkh), key)
+        } else if let Some(key) = body.strip_prefix("pkh(").and_then(|rest| rest.strip_suffix(')')) {
+            (Some(ScriptKind::Pkh), key)
+        } else if let Some(key) = body.strip_prefix("tr(").and_then(|rest| rest.strip_suffix(')')) {
+            if key.contains(',') {
+                return Err(invalid("taproot script trees are not supported".to_string()));
+            }
+            (Some(ScriptKind::Tr), key)
+        } else if body.contains('(') {
+            return Err(invalid("expected pkh(), wpkh(), sh(wpkh()) or tr() around a single key".to_string()));
+        } else {
+            (None, body)
+        };
+
+        // The origin only records where the key came from
+        let key = match key.strip_prefix('[') {
+            Some(rest) => rest.split_once(']').ok_or_else(|| invalid("unclosed key origin".to_string()))?.1,
+            None => key,
+        };
+        let mut steps = key.split('/');
+        let (key, implied) = decode_key(steps.next().unwrap_or_default(), network).map_err(invalid)?;
+        let mut path = Vec::new();
+        let mut wildcard = false;
+        for step in steps {
+            if wildcard {
+                return Err(invalid("`*` must be the last step".to_string()));
+            }
+            if step.ends_with(['\'', 'h', 'H']) {
+                return Err(invalid("hardened steps need the private key".to_string()));
+            }
+            if step == "*" {
+                wildcard = true;
+                continue;
+            }
+            let index = step.parse().map_err(|_| invalid(format!("invalid step `{}`", step)))?;
+            path.push(ChildNumber::from_normal_idx(index).map_err(|e| invalid(e.to_string()))?);
+        }
+
+        let source = descriptor.to_string();
+        Ok(match kind {
+            Some(kind) => vec![WatchDescriptor { source, kind, key, path, wildcard }],
+            None if path.is_empty() && !wildcard => [0, 1].into_iter()
+                .map(|chain| WatchDescriptor {
+                    source: format!("{}/{}/*", source, chain),
+                    kind: implied,
+                    key,
+                    path: vec![ChildNumber::Normal { index: chain }],
+                    wildcard: true,
+                })
+                .collect(),
+            None => vec![WatchDescriptor { source, kind: implied, key, path, wildcard }],
+        })
+    }
+
+    /// The output script of child `index`, or of the key itself without a wildcard.
+    pub fn script_at(&self, secp: &Secp256k1<VerifyOnly>, index: u32) -> ScriptBuf {
+        let mut path = self.path.clone();
+        if self.wildcard {
+            path.push(ChildNumber::Normal { index });
+        }
+        // Only fails for an invalid tweak, with odds of about 2^-127
+        let key = self.key.derive_pub(secp, &path).expect("unhardened derivation");
+        let public_key = PublicKey::new(key.public_key);
+        let wpubkey_hash = || public_key.wpubkey_hash().expect("derived keys are compressed");
+        match self.kind {
+            ScriptKind::Pkh => ScriptBuf::new_p2pkh(&public_key.pubkey_hash()),
+            ScriptKind::Wpkh => ScriptBuf::new_v0_p2wpkh(&wpubkey_hash()),
+            ScriptKind::ShWpkh => ScriptBuf::new_p2sh(&ScriptBuf::new_v0_p2wpkh(&wpubkey_hash()).script_hash()),
+            ScriptKind::Tr => ScriptBuf::new_v1_p2tr(secp, key.to_x_only_pub(), None),
+        }
+    }
+}
+
+/// Decodes an extended public key of `network` under any of the
+/// `KEY_VERSIONS`, with the script kind its prefix implies.
+fn decode_key(key: &str, network: Network) -> std::result::Result<(ExtendedPubKey, ScriptKind), String> {
+    let mut data = base58::decode_check(key).map_err(|e| format!("expected an extended public key: {}", e))?;
+    let (_, key_network, kind) = KEY_VERSIONS.iter()
+        .find(|(version, _, _)| data.starts_with(version))
+        .ok_or("expected an extended public key: unknown version")?;
+    // Testnet keys serve every test network
+    if (*key_network == Network::Bitcoin) != (network == Network::Bitcoin) {
+        return Err(format!("the key is for {}, not {}", key_network, network));
+    }
+    let standard = if *key_network == Network::Bitcoin { KEY_VERSIONS[0].0 } else { KEY_VERSIONS[3].0 };
+    if data.len() >= 4 {
+        data[..4].copy_from_slice(&standard);
+    }
+    let key = ExtendedPubKey::decode(&data).map_err(|e| format!("expected an extended public key: {}", e))?;
+    Ok((key, *kind))
+}
+
+/// The 8-character BIP380 checksum of `descriptor`, or `None` if it holds a
+/// character descriptors cannot.
+fn descriptor_checksum(descriptor: &str) -> Option<String> {
+    let polymod = |checksum: u64, value: u64| {
+        let top = checksum >> 35;
+        let mut checksum = ((checksum & 0x7ffffffff) << 5) ^ value;
+        for (bit, generator) in CHECKSUM_GENERATOR.iter().enumerate() {
+            if (top >> bit) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+        checksum
+    };
+
+    let mut checksum = 1;
+    let mut groups = Vec::new();
+    for c in descriptor.chars() {
+        let position = INPUT_CHARSET.find(c)? as u64;
+        checksum = polymod(checksum, position & 31);
+        groups.push(position >> 5);
+        if groups.len() == 3 {
+            checksum = polymod(checksum, groups[0] * 9 + groups[1] * 3 + groups[2]);
+            groups.clear();
+        }
+    }
+    match groups[..] {
+        [first] => checksum = polymod(checksum, first),
+        [first, second] => checksum = polymod(checksum, first * 3 + second),
+        _ => {}
+    }
+    for _ in 0..8 {
+        checksum = polymod(checksum, 0);
+    }
+    checksum ^= 1;
+    Some((0..8).map(|i| CHECKSUM_CHARSET[((checksum >> (5 * (7 - i))) & 31) as usize] as char).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // BIP84 test vectors, account 0 of the `abandon ... about` mnemonic
+    const ZPUB: &str = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+    const VPUB: &str = "vpub5Y6cjg78GGuNLsaPhmYsiw4gYX3HoQiRBiSwDaBXKUafCt9bNwWQiitDk5VZ5BVxYnQdwoTyXSs2JHRPAgjAvtbBrf8ZhDYe2jWAqvZVnsc";
+
+    fn watchlist(descriptor: &str, network: Network, gap_limit: u32) -> Watchlist {
+        let descriptors = WatchDescriptor::parse(descriptor, network).unwrap();
+        Watchlist::new(HashSet::new(), descriptors, gap_limit, network, AddressFormat::Canonical)
+    }
+
+    fn address(descriptor: &WatchDescriptor, index: u32, network: Network) -> String {
+        extract_address(descriptor.script_at(&Secp256k1::verification_only(), index), network).unwrap()
+    }
+
+    /// `key` re-encoded under the plain tpub version.
+    fn tpub_of(key: &str) -> String {
+        let mut data = base58::decode_check(key).unwrap();
+        data[..4].copy_from_slice(&KEY_VERSIONS[3].0);
+        base58::encode_check(&data)
+    }
+
+    #[test]
+    fn derives_the_bip84_addresses_of_a_bare_testnet_key() {
+        let [receive, change] = &WatchDescriptor::parse(VPUB, Network::Testnet).unwrap()[..] else { panic!("expected two chains") };
+        assert_eq!(address(receive, 0, Network::Testnet), "tb1q6rz28mcfaxtmd6v789l9rrlrusdprr9pqcpvkl");
+        assert_eq!(address(receive, 1, Network::Testnet), "tb1qd7spv5q28348xl4myc8zmh983w5jx32cjhkn97");
+        assert_eq!(address(change, 0, Network::Testnet), "tb1q9u62588spffmq4dzjxsr5l297znf3z6j5p2688");
+
+        let [receive, _] = &WatchDescriptor::parse(ZPUB, Network::Bitcoin).unwrap()[..] else { panic!("expected two chains") };
+        assert_eq!(address(receive, 0, Network::Bitcoin), "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+        assert_eq!(address(receive, 1, Network::Bitcoin), "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g");
+        assert!(WatchDescriptor::parse(ZPUB, Network::Testnet).is_err());
+    }
+
+    #[test]
+    fn descriptors_pick_the_script_around_the_key() {
+        let tpub = tpub_of(VPUB);
+        let wpkh = &WatchDescriptor::parse(&format!("wpkh([73c5da0a/84'/1'/0']{}/0/*)", tpub), Network::Regtest).unwrap()[0];
+        let testnet = |d: &WatchDescriptor, i| extract_address(d.script_at(&Secp256k1::verification_only(), i), Network::Testnet).unwrap();
+        assert_eq!(testnet(wpkh, 0), "tb1q6rz28mcfaxtmd6v789l9rrlrusdprr9pqcpvkl");
+
+        let script = |descriptor: String| WatchDescriptor::parse(&descriptor, Network::Regtest).unwrap()[0].script_at(&Secp256k1::verification_only(), 0);
+        assert!(script(format!("pkh({}/0/*)", tpub)).is_p2pkh());
+        assert_eq!(
+            script(format!("sh(wpkh({}/0/*))", tpub)),
+            ScriptBuf::new_p2sh(&script(format!("wpkh({}/0/*)", tpub)).script_hash())
+        );
+        assert!(script(format!("tr({}/0/*)", tpub)).is_v1_p2tr());
+        // Without a wildcard the key path names a single address
+        assert_eq!(script(format!("wpkh({}/0/0)", tpub)), script(format!("wpkh({}/0/*)", tpub)));
+
+        for invalid in [
+            format!("wpkh({}/0'/*)", tpub),
+            format!("wpkh({}/*/0)", tpub),
+            format!("tr({}/0/*,pk({}))", tpub, tpub),
+            format!("wsh(multi(1,{}/0/*))", tpub),
+            "wpkh(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5)".to_string(),
+        ] {
+            assert!(matches!(WatchDescriptor::parse(&invalid, Network::Regtest), Err(IndexerError::Config(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn checksums_are_verified_when_given() {
+        assert_eq!(descriptor_checksum("raw(deadbeef)").as_deref(), Some("89f8spxm"));
+        let tpub = tpub_of(VPUB);
+        let descriptor = format!("wpkh({}/0/*)", tpub);
+        let checksum = descriptor_checksum(&descriptor).unwrap();
+        assert!(WatchDescriptor::parse(&format!("{}#{}", descriptor, checksum), Network::Regtest).is_ok());
+        let wrong = format!("{}#{}", descriptor, checksum.replace(|c| c != 'q', "q"));
+        assert!(WatchDescriptor::parse(&wrong, Network::Regtest).is_err());
+    }
+
+    #[test]
+    fn the_window_moves_past_addresses_seen_in_blocks() {
+        let list = watchlist(VPUB, Network::Testnet, 3);
+        let [receive, _] = &list.descriptors[..] else { panic!("expected two chains") };
+        let receive_at = |index| address(receive, index, Network::Testnet);
+        // Three addresses of each chain to begin with
+        assert_eq!(list.addresses().len(), 6);
+        assert!(!list.contains(&receive_at(3)));
+
+        assert!(list.contains(&receive_at(2)));
+        assert_eq!(list.addresses().len(), 9);
+        assert!(list.contains(&receive_at(5)));
+        assert!(!list.contains(&receive_at(9)));
+        assert!(!list.contains("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx"));
+    }
+}
@@ -0,0 +1,820 @@
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use bitcoincore_rpc::bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::future::join_all;
+use log::{debug, info, warn};
+use rand::Rng;
+use reqwest::header::{HeaderName, HeaderValue};
+
+use crate::{IndexerError, Result};
+
+pub const DEFAULT_USER_AGENT: &str = concat!("sova-network-indexer/", env!("CARGO_PKG_VERSION"));
+
+// Set by the indexer itself, so `with_header` cannot override them
+const RESERVED_HEADERS: [&str; 5] = ["content-type", "content-encoding", "idempotency-key", "x-signature", "user-agent"];
+
+/// Delivers payloads to the configured webhook endpoint, retrying transient
+/// failures with exponential backoff.
+pub struct Webhook {
+    url: String,
+    // Shared by every destination built from the same `http_client`
+    client: reqwest::Client,
+    max_retries: u32,
+    base_delay: Duration,
+    // Marked sensitive so it is redacted from any debug output
+    auth: Option<(HeaderName, HeaderValue)>,
+    user_agent: HeaderValue,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    hmac_secret: Option<Vec<u8>>,
+    compress: bool,
+    // When set, a 2xx response must also carry `{"<field>": true}`
+    ack_field: Option<String>,
+}
+
+/// Why a single POST attempt failed.
+enum DeliveryFailure {
+    /// Network errors and 5xx/429 responses, worth retrying.
+    Transient(String),
+    /// Any other non-2xx response; the receiver rejected the payload.
+    Permanent(String),
+}
+
+impl Webhook {
+    pub fn new(url: &str, max_retries: u32, base_delay: Duration) -> Webhook {
+        Webhook {
+            url: url.to_string(),
+            client: reqwest::Client::new(),
+            max_retries,
+            base_delay,
+            auth: None,
+            user_agent: HeaderValue::from_static(DEFAULT_USER_AGENT),
+            headers: Vec::new(),
+            hmac_secret: None,
+            compress: false,
+            ack_field: None,
+        }
+    }
+
+    /// Attaches `name: value` to every POST, for receivers that require a
+    /// token or shared secret.
+    pub fn with_auth(mut self, name: &str, value: &str) -> Result<Webhook> {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| IndexerError::Config(
+            format!("Invalid webhook auth header name `{}`", name)
+        ))?;
+        let mut value = HeaderValue::from_str(value).map_err(|_| IndexerError::Config(
+            "Webhook auth value is not a valid header value".to_string()
+        ))?;
+        value.set_sensitive(true);
+        self.auth = Some((name, value));
+        Ok(self)
+    }
+
+    /// Posts through `client`, typically from `http_client` and shared with
+    /// the other destinations, so connections are kept alive and reused.
+    pub fn with_client(mut self, client: reqwest::Client) -> Webhook {
+        self.client = client;
+        self
+    }
+
+    /// Sends `user_agent` as the `User-Agent` instead of `DEFAULT_USER_AGENT`.
+    pub fn with_user_agent(mut self, user_agent: &str) -> Result<Webhook> {
+        self.user_agent = HeaderValue::from_str(user_agent).map_err(|_| IndexerError::Config(
+            format!("Invalid webhook user agent `{}`", user_agent)
+        ))?;
+        Ok(self)
+    }
+
+    /// Attaches `name: value` to every POST, e.g. for an API gateway that
+    /// routes on it. Headers the indexer sets itself are rejected.
+    pub fn with_header(mut self, name: &str, value: &str) -> Result<Webhook> {
+        let invalid = |reason: &str| IndexerError::Config(format!("Invalid webhook header `{}`: {}", name, reason));
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid("not a header name"))?;
+        if RESERVED_HEADERS.contains(&name.as_str()) {
+            return Err(invalid("set by the indexer"));
+        }
+        let value = HeaderValue::from_str(value).map_err(|_| invalid("not a valid header value"))?;
+        self.headers.push((name, value));
+        Ok(self)
+    }
+
+    /// Signs every body with HMAC-SHA256 under `secret`, sent hex-encoded in
+    /// the `X-Signature` header.
+    pub fn with_hmac_secret(mut self, secret: &str) -> Webhook {
+        self.hmac_secret = Some(secret.as_bytes().to_vec());
+        self
+    }
+
+    /// Gzip-compresses every body and marks it with `Content-Encoding: gzip`.
+    pub fn with_compression(mut self) -> Webhook {
+        self.compress = true;
+        self
+    }
+
+    /// Requires 2xx responses to acknowledge the payload with `{"<field>": true}`;
+    /// anything else is treated as a transient failure and retried.
+    pub fn with_ack_field(mut self, field: &str) -> Webhook {
+        self.ack_field = Some(field.to_string());
+        self
+    }
+
+    /// Posts the JSON `payload`, retrying transient failures. Every attempt
+    /// carries `idempotency_key` in the `Idempotency-Key` header.
+    pub async fn send(&self, payload: &[u8], idempotency_key: &str) -> Result<()> {
+        self.send_with_retries(payload, idempotency_key, self.max_retries).await
+    }
+
+    async fn send_with_retries(&self, payload: &[u8], idempotency_key: &str, max_retries: u32) -> Result<()> {
+        // The signature covers exactly the bytes posted
+        let body = if self.compress { gzip(payload) } else { payload.to_vec() };
+        let signature = self.hmac_secret.as_ref().map(|secret| sign(secret, &body));
+
+        let mut attempt = 0;
+        loop {
+            let failure = match self.post(&body, signature.as_deref(), idempotency_key).await {
+                Ok(()) => return Ok(()),
+                Err(failure) => failure,
+            };
+
+            match failure {
+                DeliveryFailure::Permanent(msg) => return Err(IndexerError::WebhookFailed(msg)),
+                DeliveryFailure::Transient(msg) if attempt >= max_retries => {
+                    return Err(IndexerError::WebhookFailed(
+                        format!("{} (gave up after {} retries)", msg, attempt)
+                    ));
+                }
+                DeliveryFailure::Transient(msg) => {
+                    let delay = backoff_delay(self.base_delay, attempt);
+                    attempt += 1;
+                    warn!(attempt = attempt, delay_ms = delay.as_millis() as u64;
+                        "Webhook delivery failed: {}. Retry {}/{} in {:?}",
+                        msg,
+                        attempt,
+                        max_retries,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+
+    async fn post(
+        &self,
+        body: &[u8],
+        signature: Option<&str>,
+        idempotency_key: &str,
+    ) -> std::result::Result<(), DeliveryFailure> {
+        let mut request = self.client.post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("Idempotency-Key", idempotency_key)
+            .header(reqwest::header::USER_AGENT, &self.user_agent)
+            .body(body.to_vec());
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        if let Some((name, value)) = &self.auth {
+            request = request.header(name, value);
+        }
+        if self.compress {
+            request = request.header(reqwest::header::CONTENT_ENCODING, "gzip");
+        }
+        if let Some(signature) = signature {
+            request = request.header("X-Signature", signature);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| DeliveryFailure::Transient(format!("Network error: {}", e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            match &self.ack_field {
+                Some(field) => check_ack(response, field).await,
+                None => Ok(()),
+            }
+        } else if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            Err(DeliveryFailure::Transient(format!("Status code: {}", status)))
+        } else {
+            Err(DeliveryFailure::Permanent(format!("Status code: {}", status)))
+        }
+    }
+}
+
+async fn check_ack(response: reqwest::Response, field: &str) -> std::result::Result<(), DeliveryFailure> {
+    let body = response.text().await
+        .map_err(|e| DeliveryFailure::Transient(format!("Failed to read acknowledgment: {}", e)))?;
+    let acknowledged = serde_json::from_str::<serde_json::Value>(&body)
+        .is_ok_and(|json| json.get(field) == Some(&serde_json::Value::Bool(true)));
+    if acknowledged {
+        Ok(())
+    } else {
+        Err(DeliveryFailure::Transient(format!("Receiver did not acknowledge with `{}: true`: {}", field, body)))
+    }
+}
+
+/// Builds the HTTP client webhook POSTs go through. Idle connections are
+/// kept for `pool_idle_timeout`, at most `pool_max_idle_per_host` per
+/// destination if set, and HTTPS receivers can negotiate HTTP/2. A single
+/// POST taking longer than `timeout` fails like a network error and is
+/// retried. `http2_prior_knowledge` speaks HTTP/2 over plain HTTP too, for
+/// receivers known to support it.
+pub fn http_client(
+    timeout: Option<Duration>,
+    pool_idle_timeout: Duration,
+    pool_max_idle_per_host: Option<usize>,
+    http2_prior_knowledge: bool,
+) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder().pool_idle_timeout(pool_idle_timeout);
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(max_idle) = pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max_idle);
+    }
+    if http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+    builder.build().map_err(IndexerError::Network)
+}
+
+fn gzip(body: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body).expect("writing to a Vec never fails");
+    encoder.finish().expect("writing to a Vec never fails")
+}
+
+/// Hex-encoded HMAC-SHA256 of `body` under `secret`.
+fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret);
+    engine.input(body);
+    hex::encode(hmac::Hmac::<sha256::Hash>::from_engine(engine).to_byte_array())
+}
+
+/// Stops posting to a destination after `threshold` consecutive failed
+/// deliveries. Once `cooldown` has passed a single probe request is let
+/// through: if it succeeds the circuit closes, otherwise it stays open for
+/// another cooldown.
+struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<CircuitState>,
+}
+
+#[derive(Default)]
+struct CircuitState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    // Set while the probe is in flight, so only one request gets through
+    probing: bool,
+}
+
+/// How `CircuitBreaker::admit` lets a delivery through.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Admission {
+    Closed,
+    Probe,
+}
+
+impl CircuitBreaker {
+    fn new(threshold: u32, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker { threshold, cooldown, state: Mutex::new(CircuitState::default()) }
+    }
+
+    fn admit(&self) -> Result<Admission> {
+        let mut state = self.state.lock().unwrap();
+        let Some(opened_at) = state.opened_at else {
+            return Ok(Admission::Closed);
+        };
+        let waited = opened_at.elapsed();
+        if waited < self.cooldown || state.probing {
+            return Err(IndexerError::WebhookFailed(format!(
+                "Circuit open after {} consecutive failures, next probe in {:?}",
+                state.consecutive_failures,
+                self.cooldown.saturating_sub(waited)
+            )));
+        }
+        state.probing = true;
+        Ok(Admission::Probe)
+    }
+
+    fn record(&self, url: &str, delivered: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.probing = false;
+        if delivered {
+            if state.opened_at.take().is_some() {
+                info!("Circuit closed for {}, resuming delivery", url);
+            }
+            state.consecutive_failures = 0;
+            return;
+        }
+
+        state.consecutive_failures += 1;
+        if state.opened_at.is_some() || state.consecutive_failures >= self.threshold {
+            warn!("Circuit open for {} after {} consecutive failures, pausing delivery for {:?}",
+                url, state.consecutive_failures, self.cooldown);
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+/// Fans each payload out to several webhook destinations at once. Every
+/// destination retries independently; the payload only counts as failed
+/// once at least `failure_quorum` destinations have given up on it.
+pub struct Webhooks {
+    destinations: Vec<Webhook>,
+    failure_quorum: usize,
+    // Limit on each destination's delivery, retries included
+    delivery_timeout: Option<Duration>,
+    // One per destination, in the same order, when enabled
+    circuits: Vec<CircuitBreaker>,
+}
+
+impl Webhooks {
+    pub fn new(destinations: Vec<Webhook>, failure_quorum: usize) -> Result<Webhooks> {
+        if destinations.is_empty() {
+            return Err(IndexerError::Config("At least one --webhook-url is required".to_string()));
+        }
+        if failure_quorum == 0 || failure_quorum > destinations.len() {
+            return Err(IndexerError::Config(format!(
+                "--webhook-failure-quorum must be between 1 and the number of webhook URLs ({}), got {}",
+                destinations.len(),
+                failure_quorum
+            )));
+        }
+        Ok(Webhooks { destinations, failure_quorum, delivery_timeout: None, circuits: Vec::new() })
+    }
+
+    /// Gives up on a destination once its delivery, retries included, has
+    /// taken `timeout`, so one stalled receiver cannot hold up the others.
+    pub fn with_delivery_timeout(mut self, timeout: Duration) -> Webhooks {
+        self.delivery_timeout = Some(timeout);
+        self
+    }
+
+    /// Pauses delivery to a destination for `cooldown` once `threshold`
+    /// deliveries in a row have failed, instead of posting every block to a
+    /// receiver that is down. Paused deliveries fail straight away.
+    pub fn with_circuit_breaker(mut self, threshold: u32, cooldown: Duration) -> Webhooks {
+        self.circuits = self.destinations.iter().map(|_| CircuitBreaker::new(threshold, cooldown)).collect();
+        self
+    }
+
+    async fn send_to(&self, index: usize, payload: &[u8], idempotency_key: &str) -> Result<()> {
+        let webhook = &self.destinations[index];
+        let Some(circuit) = self.circuits.get(index) else {
+            return self.send_with_timeout(webhook, payload, idempotency_key, Admission::Closed).await;
+        };
+
+        let admission = circuit.admit()?;
+        let result = self.send_with_timeout(webhook, payload, idempotency_key, admission).await;
+        circuit.record(&webhook.url, result.is_ok());
+        result
+    }
+
+    async fn send_with_timeout(
+        &self,
+        webhook: &Webhook,
+        payload: &[u8],
+        idempotency_key: &str,
+        admission: Admission,
+    ) -> Result<()> {
+        let delivery = async {
+            match admission {
+                Admission::Closed => webhook.send(payload, idempotency_key).await,
+                // The probe is a single request, not a full round of retries
+                Admission::Probe => webhook.send_with_retries(payload, idempotency_key, 0).await,
+            }
+        };
+        let Some(timeout) = self.delivery_timeout else {
+            return delivery.await;
+        };
+        tokio::time::timeout(timeout, delivery)
+            .await
+            .unwrap_or_else(|_| Err(IndexerError::WebhookFailed(format!("Delivery timed out after {:?}", timeout))))
+    }
+
+    /// Posts the JSON `payload` to every destination.
+    pub async fn send(&self, payload: &[u8], idempotency_key: &str) -> Result<()> {
+        let mut results = join_all(
+            (0..self.destinations.len()).map(|index| self.send_to(index, payload, idempotency_key))
+        ).await;
+        if results.len() == 1 {
+            return results.remove(0);
+        }
+
+        let mut delivered = Vec::new();
+        let mut failed = Vec::new();
+        for (webhook, result) in self.destinations.iter().zip(results) {
+            match result {
+                Ok(()) => delivered.push(webhook.url.as_str()),
+                Err(IndexerError::WebhookFailed(msg)) => failed.push(format!("{} ({})", webhook.url, msg)),
+                Err(e) => failed.push(format!("{} ({})", webhook.url, e)),
+            }
+        }
+
+        if failed.is_empty() {
+            debug!("Delivered to {}", delivered.join(", "));
+            return Ok(());
+        }
+        warn!("Delivered to [{}], failed for [{}]", delivered.join(", "), failed.join(", "));
+
+        if failed.len() >= self.failure_quorum {
+            return Err(IndexerError::WebhookFailed(format!(
+                "{} of {} destinations failed: {}",
+                failed.len(),
+                self.destinations.len(),
+                failed.join(", ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Accumulates block updates so several blocks can be delivered in a single
+/// POST. A batch is ready once it holds `max_size` items or its oldest item
+/// has waited `flush_interval`.
+pub struct Batch<T> {
+    items: Vec<T>,
+    max_size: usize,
+    flush_interval: Duration,
+    opened_at: Option<Instant>,
+}
+
+impl<T> Batch<T> {
+    pub fn new(max_size: usize, flush_interval: Duration) -> Batch<T> {
+        Batch {
+            items: Vec::with_capacity(max_size),
+            max_size,
+            flush_interval,
+            opened_at: None,
+        }
+    }
+
+    pub fn push(&mut self, item: T) {
+        self.opened_at.get_or_insert_with(Instant::now);
+        self.items.push(item);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.items.len() >= self.max_size
+            || self.opened_at.is_some_and(|opened_at| opened_at.elapsed() >= self.flush_interval)
+    }
+
+    /// Removes and returns the pending items in the order they were pushed.
+    pub fn take(&mut self) -> Vec<T> {
+        self.opened_at = None;
+        std::mem::take(&mut self.items)
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+}
+
+/// Exponential backoff with jitter: the delay for `attempt` is drawn
+/// uniformly from `[base * 2^attempt / 2, base * 2^attempt]`.
+fn backoff_delay(base: Duration, attempt: u32) -> Duration {
+    let ceiling = base.saturating_mul(1 << attempt.min(16));
+    let half = ceiling / 2;
+    let jitter_ms = rand::thread_rng().gen_range(0..=half.as_millis() as u64);
+    half + Duration::from_millis(jitter_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::MockReceiver;
+    use serde::Serialize;
+
+    const KEY: &str = "block-00ab";
+
+    fn json(payload: &impl Serialize) -> Vec<u8> {
+        serde_json::to_vec(payload).unwrap()
+    }
+
+    fn webhook(url: &str, max_retries: u32) -> Webhook {
+        Webhook::new(url, max_retries, Duration::from_millis(1))
+    }
+
+    #[tokio::test]
+    async fn failing_destination_only_fails_the_payload_at_quorum() {
+        let healthy = MockReceiver::start().await;
+        let broken = MockReceiver::with_statuses(vec![500; 10]).await;
+        let destinations = || vec![webhook(&healthy.url, 0), webhook(&broken.url, 0)];
+        let update = serde_json::json!({"height": 1});
+
+        Webhooks::new(destinations(), 2).unwrap().send(&json(&update), KEY).await.unwrap();
+        let result = Webhooks::new(destinations(), 1).unwrap().send(&json(&update), KEY).await;
+
+        assert!(matches!(result, Err(IndexerError::WebhookFailed(msg)) if msg.contains(&broken.url)));
+        assert_eq!(healthy.bodies(), vec![update.clone(), update]);
+        assert_eq!(broken.requests().len(), 2);
+        assert!(Webhooks::new(destinations(), 3).is_err());
+    }
+
+    #[tokio::test]
+    async fn destinations_are_delivered_to_concurrently() {
+        let delay = Duration::from_millis(300);
+        let receivers = [
+            MockReceiver::slow(delay).await,
+            MockReceiver::slow(delay).await,
+            MockReceiver::slow(delay).await,
+        ];
+        let webhooks = Webhooks::new(receivers.iter().map(|r| webhook(&r.url, 0)).collect(), 1).unwrap();
+
+        let started = Instant::now();
+        webhooks.send(&json(&serde_json::json!({"height": 1})), KEY).await.unwrap();
+
+        // Close to the slowest destination rather than the sum of all three
+        assert!(started.elapsed() < delay * 2, "took {:?}", started.elapsed());
+        assert!(receivers.iter().all(|r| r.requests().len() == 1));
+    }
+
+    #[tokio::test]
+    async fn stalled_destinations_time_out_and_count_towards_the_quorum() {
+        let healthy = MockReceiver::start().await;
+        let stalled = MockReceiver::slow(Duration::from_secs(10)).await;
+        let destinations = || vec![webhook(&healthy.url, 0), webhook(&stalled.url, 0)];
+        let timeout = Duration::from_millis(200);
+
+        let started = Instant::now();
+        let tolerated = Webhooks::new(destinations(), 2).unwrap().with_delivery_timeout(timeout)
+            .send(&json(&serde_json::json!({"height": 1})), KEY).await;
+        let failed = Webhooks::new(destinations(), 1).unwrap().with_delivery_timeout(timeout)
+            .send(&json(&serde_json::json!({"height": 1})), KEY).await;
+
+        assert!(tolerated.is_ok());
+        assert!(matches!(failed, Err(IndexerError::WebhookFailed(msg)) if msg.contains("timed out")));
+        assert!(started.elapsed() < Duration::from_secs(2), "took {:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn circuit_breaker_pauses_a_flapping_receiver_and_probes_after_cooldown() {
+        // Two failed deliveries of two attempts each, a failed probe, then recovery
+        let receiver = MockReceiver::with_statuses(vec![500, 500, 500, 500, 500]).await;
+        let cooldown = Duration::from_millis(200);
+        let webhooks = Webhooks::new(vec![webhook(&receiver.url, 1)], 1).unwrap()
+            .with_circuit_breaker(2, cooldown);
+        let update = serde_json::json!({"height": 1});
+        let circuit_open = |result: Result<()>| matches!(result, Err(IndexerError::WebhookFailed(msg)) if msg.contains("Circuit open"));
+
+        assert!(webhooks.send(&json(&update), KEY).await.is_err());
+        assert!(webhooks.send(&json(&update), KEY).await.is_err());
+        assert_eq!(receiver.requests().len(), 4);
+
+        // Open: fails without reaching the receiver
+        assert!(circuit_open(webhooks.send(&json(&update), KEY).await));
+        assert_eq!(receiver.requests().len(), 4);
+
+        // The probe is a single request; failing it reopens the circuit
+        tokio::time::sleep(cooldown).await;
+        assert!(!circuit_open(webhooks.send(&json(&update), KEY).await));
+        assert_eq!(receiver.requests().len(), 5);
+        assert!(circuit_open(webhooks.send(&json(&update), KEY).await));
+
+        tokio::time::sleep(cooldown).await;
+        webhooks.send(&json(&update), KEY).await.unwrap();
+        webhooks.send(&json(&update), KEY).await.unwrap();
+        assert_eq!(receiver.requests().len(), 7);
+    }
+
+    #[test]
+    fn backoff_delay_grows_exponentially_within_jitter_bounds() {
+        let base = Duration::from_millis(100);
+        for attempt in 0..5 {
+            let ceiling = base * 2u32.pow(attempt);
+            let delay = backoff_delay(base, attempt);
+            assert!(delay >= ceiling / 2 && delay <= ceiling, "attempt {}: {:?}", attempt, delay);
+        }
+    }
+
+    #[tokio::test]
+    async fn batches_blocks_into_ceil_n_over_k_posts() {
+        let receiver = MockReceiver::start().await;
+        let webhook = webhook(&receiver.url, 0);
+        let mut batch = Batch::new(3, Duration::from_secs(60));
+
+        for height in 0..10 {
+            batch.push(serde_json::json!({"height": height}));
+            if batch.is_ready() {
+                webhook.send(&json(&batch.take()), KEY).await.unwrap();
+            }
+        }
+        webhook.send(&json(&batch.take()), KEY).await.unwrap();
+
+        let bodies = receiver.bodies();
+        assert_eq!(bodies.len(), 4);
+        let heights: Vec<_> = bodies.iter()
+            .flat_map(|body| body.as_array().unwrap().clone())
+            .map(|update| update["height"].as_i64().unwrap())
+            .collect();
+        assert_eq!(heights, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn batch_is_ready_after_flush_interval() {
+        let mut batch = Batch::new(100, Duration::ZERO);
+        assert!(!batch.is_ready());
+
+        batch.push(1);
+
+        assert!(batch.is_ready());
+        assert_eq!(batch.take(), vec![1]);
+        assert!(!batch.is_ready());
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_retried() {
+        let receiver = MockReceiver::with_statuses(vec![503, 503]).await;
+
+        webhook(&receiver.url, 3).send(&json(&serde_json::json!({"height": 1})), KEY).await.unwrap();
+
+        let requests = receiver.requests();
+        assert_eq!(requests.len(), 3);
+        for request in requests {
+            assert_eq!(request.method, "POST");
+            assert_eq!(request.path, "/hook");
+            assert_eq!(request.header("content-type"), Some("application/json"));
+            assert_eq!(request.json(), serde_json::json!({"height": 1}));
+            // Every retry carries the same key, so the receiver can drop duplicates
+            assert_eq!(request.header("idempotency-key"), Some(KEY));
+        }
+    }
+
+    #[tokio::test]
+    async fn auth_header_is_attached_but_redacted() {
+        let receiver = MockReceiver::start().await;
+        let webhook = webhook(&receiver.url, 0).with_auth("Authorization", "Bearer s3cret").unwrap();
+
+        webhook.send(&json(&serde_json::json!({"height": 1})), KEY).await.unwrap();
+
+        assert_eq!(receiver.requests()[0].header("authorization"), Some("Bearer s3cret"));
+        let (_, value) = webhook.auth.as_ref().unwrap();
+        assert!(!format!("{:?}", value).contains("s3cret"));
+        assert!(webhook.with_auth("bad header", "x").is_err());
+    }
+
+    #[tokio::test]
+    async fn user_agent_and_extra_headers_are_attached() {
+        let receiver = MockReceiver::start().await;
+        webhook(&receiver.url, 0).send(&json(&serde_json::json!({"height": 1})), KEY).await.unwrap();
+        let webhook = webhook(&receiver.url, 0)
+            .with_user_agent("gateway-client/2").unwrap()
+            .with_header("X-Route", "utxos").unwrap()
+            .with_header("X-Tenant", "sova").unwrap();
+
+        webhook.send(&json(&serde_json::json!({"height": 2})), KEY).await.unwrap();
+
+        let requests = receiver.requests();
+        assert_eq!(requests[0].header("user-agent"), Some(DEFAULT_USER_AGENT));
+        assert!(DEFAULT_USER_AGENT.starts_with("sova-network-indexer/"));
+        assert_eq!(requests[1].header("user-agent"), Some("gateway-client/2"));
+        assert_eq!((requests[1].header("x-route"), requests[1].header("x-tenant")), (Some("utxos"), Some("sova")));
+        assert!(webhook.with_header("Idempotency-Key", "x").is_err());
+        assert!(Webhook::new(&receiver.url, 0, Duration::ZERO).with_header("bad header", "x").is_err());
+        assert!(Webhook::new(&receiver.url, 0, Duration::ZERO).with_user_agent("bad\nagent").is_err());
+    }
+
+    #[tokio::test]
+    async fn destinations_sharing_a_client_reuse_its_connections() {
+        let receiver = MockReceiver::start().await;
+        let client = http_client(Some(Duration::from_secs(5)), Duration::from_secs(90), None, false).unwrap();
+        let webhook = webhook(&receiver.url, 0).with_client(client.clone());
+
+        for height in 0..5 {
+            webhook.send(&json(&serde_json::json!({"height": height})), KEY).await.unwrap();
+        }
+        let other = Webhook::new(&receiver.url, 0, Duration::ZERO).with_client(client);
+        other.send(&json(&serde_json::json!({"height": 5})), KEY).await.unwrap();
+
+        assert_eq!(receiver.requests().len(), 6);
+        assert_eq!(receiver.connections(), 1);
+    }
+
+    #[tokio::test]
+    async fn posts_slower_than_the_client_timeout_are_retried() {
+        let receiver = MockReceiver::slow(Duration::from_millis(300)).await;
+        let client = http_client(Some(Duration::from_millis(50)), Duration::from_secs(90), None, false).unwrap();
+
+        let result = webhook(&receiver.url, 1).with_client(client).send(&json(&serde_json::json!({"height": 1})), KEY).await;
+
+        assert!(matches!(&result, Err(IndexerError::WebhookFailed(msg)) if msg.contains("timed out")), "{:?}", result);
+        assert_eq!(receiver.requests().len(), 2);
+    }
+
+    /// Posts per second with a new client, and so a new connection, for every
+    /// POST (as `send` used to) against a shared `http_client`. Run with
+    /// `cargo test --release webhook_connection_reuse -- --ignored --nocapture`.
+    #[tokio::test]
+    #[ignore = "benchmark"]
+    async fn webhook_connection_reuse_benchmark() {
+        let receiver = MockReceiver::start().await;
+        let payload = json(&serde_json::json!({"height": 1, "utxo_updates": vec!["0".repeat(64); 100]}));
+        let posts = 200;
+
+        let started = Instant::now();
+        for _ in 0..posts {
+            webhook(&receiver.url, 0).send(&payload, KEY).await.unwrap();
+        }
+        let fresh = started.elapsed();
+
+        let client = http_client(None, Duration::from_secs(90), None, false).unwrap();
+        let shared = webhook(&receiver.url, 0).with_client(client);
+        let started = Instant::now();
+        for _ in 0..posts {
+            shared.send(&payload, KEY).await.unwrap();
+        }
+        let reused = started.elapsed();
+
+        println!(
+            "client per post: {:.0} posts/sec, shared client: {:.0} posts/sec",
+            posts as f64 / fresh.as_secs_f64(),
+            posts as f64 / reused.as_secs_f64()
+        );
+    }
+
+    #[test]
+    fn sign_matches_rfc_4231_test_vector() {
+        assert_eq!(
+            sign(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn signature_covers_the_exact_body_sent() {
+        let receiver = MockReceiver::start().await;
+        let webhook = webhook(&receiver.url, 0).with_hmac_secret("shared-secret");
+
+        webhook.send(&json(&serde_json::json!({"height": 1, "hash": "00ab"})), KEY).await.unwrap();
+
+        let request = &receiver.requests()[0];
+        let expected = sign(b"shared-secret", &request.body);
+        assert_eq!(request.header("x-signature"), Some(expected.as_str()));
+        assert_eq!(request.body, br#"{"hash":"00ab","height":1}"#);
+    }
+
+    #[tokio::test]
+    async fn compressed_bodies_decompress_to_the_original_json() {
+        use std::io::Read;
+
+        let receiver = MockReceiver::start().await;
+        let update = serde_json::json!({"height": 1, "utxo_updates": vec!["00ab"; 100]});
+
+        webhook(&receiver.url, 0).with_compression().send(&json(&update), KEY).await.unwrap();
+
+        let request = &receiver.requests()[0];
+        assert_eq!(request.header("content-encoding"), Some("gzip"));
+        assert!(request.body.len() < serde_json::to_vec(&update).unwrap().len());
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(&request.body[..]).read_to_string(&mut json).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&json).unwrap(), update);
+    }
+
+    #[tokio::test]
+    async fn unacknowledged_deliveries_are_retried() {
+        let receiver = MockReceiver::with_responses(vec![
+            (200, r#"{"ok": false}"#.to_string()),
+            (200, "accepted".to_string()),
+            (200, r#"{"ok": true}"#.to_string()),
+        ]).await;
+
+        webhook(&receiver.url, 3).with_ack_field("ok").send(&json(&serde_json::json!({"height": 1})), KEY).await.unwrap();
+
+        assert_eq!(receiver.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn ack_is_only_checked_when_configured() {
+        let receiver = MockReceiver::with_responses(vec![(200, r#"{"ok": false}"#.to_string())]).await;
+
+        webhook(&receiver.url, 3).send(&json(&serde_json::json!({"height": 1})), KEY).await.unwrap();
+        let result = webhook(&receiver.url, 0).with_ack_field("processed").send(&json(&serde_json::json!({"height": 1})), KEY).await;
+
+        assert!(matches!(result, Err(IndexerError::WebhookFailed(msg)) if msg.contains("processed")));
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let receiver = MockReceiver::with_statuses(vec![400]).await;
+
+        let result = webhook(&receiver.url, 3).send(&json(&serde_json::json!({"height": 1})), KEY).await;
+
+        assert!(matches!(result, Err(IndexerError::WebhookFailed(_))));
+        assert_eq!(receiver.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let receiver = MockReceiver::with_statuses(vec![503; 10]).await;
+
+        let result = webhook(&receiver.url, 2).send(&json(&serde_json::json!({"height": 1})), KEY).await;
+
+        assert!(matches!(result, Err(IndexerError::WebhookFailed(_))));
+        assert_eq!(receiver.requests().len(), 3);
+    }
+}
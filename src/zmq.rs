@@ -0,0 +1,181 @@
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use reqwest::Url;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio::sync::Notify;
+
+use crate::{IndexerError, Result};
+
+const TOPIC: &[u8] = b"hashblock";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// Frame flags
+const MORE: u8 = 0x01;
+const LONG: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+
+/// Subscribes to the `hashblock` notifications Bitcoin Core publishes with
+/// `-zmqpubhashblock=<url>` and wakes `new_blocks` on each, so a new tip is
+/// processed without waiting out the poll interval. Speaks just enough ZMTP
+/// 3.0 for that: a SUB socket with the NULL mechanism, over TCP. Errors are
+/// logged and the subscription is retried every few seconds, while polling
+/// carries on and picks up anything announced in the meantime.
+pub fn watch_blocks(url: &str, new_blocks: Arc<Notify>) -> Result<impl Future<Output = ()>> {
+    let invalid = |reason: &str| IndexerError::Config(format!("Invalid --zmq-block-url `{}`: {}", url, reason));
+    let parsed = Url::parse(url).map_err(|e| invalid(&e.to_string()))?;
+    if parsed.scheme() != "tcp" {
+        return Err(invalid("expected a tcp:// URL"));
+    }
+    let host = parsed.host_str().filter(|host| !host.is_empty()).ok_or_else(|| invalid("missing host"))?;
+    let port = parsed.port().ok_or_else(|| invalid("missing port"))?;
+    let address = format!("{}:{}", host, port);
+
+    Ok(async move {
+        loop {
+            let error = match tokio::time::timeout(CONNECT_TIMEOUT, subscribe(&address)).await {
+                Ok(Ok(mut stream)) => {
+                    info!("Subscribed to ZMQ block notifications on {}", address);
+                    loop {
+                        match read_message(&mut stream).await {
+                            Ok(message) if message.first().is_some_and(|topic| topic == TOPIC) => {
+                                debug!("ZMQ announced block {}", message.get(1).map(hex::encode).unwrap_or_default());
+                                new_blocks.notify_one();
+                            }
+                            Ok(_) => {}
+                            Err(e) => break e,
+                        }
+                    }
+                }
+                Ok(Err(e)) => e,
+                Err(_) => io::Error::new(io::ErrorKind::TimedOut, format!("no handshake after {:?}", CONNECT_TIMEOUT)),
+            };
+            warn!("ZMQ subscription to {} failed: {}, relying on polling and retrying in {:?}", address, error, RECONNECT_DELAY);
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    })
+}
+
+/// Connects, exchanges greetings and READY commands, and subscribes to `TOPIC`.
+async fn subscribe(address: &str) -> io::Result<BufStream<TcpStream>> {
+    let mut stream = BufStream::new(TcpStream::connect(address).await?);
+    stream.write_all(&greeting()).await?;
+    stream.flush().await?;
+    let mut peer = [0u8; 64];
+    stream.read_exact(&mut peer).await?;
+    if peer[0] != 0xff || peer[9] != 0x7f || peer[10] < 3 {
+        return Err(protocol_error("the peer does not speak ZMTP 3"));
+    }
+    if &peer[12..16] != b"NULL" {
+        return Err(protocol_error("the peer requires a security mechanism"));
+    }
+
+    let mut ready = vec![5];
+    ready.extend_from_slice(b"READY");
+    ready.push(11);
+    ready.extend_from_slice(b"Socket-Type");
+    ready.extend_from_slice(&3u32.to_be_bytes());
+    ready.extend_from_slice(b"SUB");
+    write_frame(&mut stream, COMMAND, &ready).await?;
+    stream.flush().await?;
+    let (flags, command) = read_frame(&mut stream).await?;
+    if flags & COMMAND == 0 || !command.starts_with(b"\x05READY") {
+        return Err(protocol_error("expected a READY command"));
+    }
+
+    // ZMTP 3.0 subscriptions are messages of 0x01 and the topic prefix
+    let mut subscription = vec![1];
+    subscription.extend_from_slice(TOPIC);
+    write_frame(&mut stream, 0, &subscription).await?;
+    stream.flush().await?;
+    Ok(stream)
+}
+
+fn greeting() -> [u8; 64] {
+    let mut greeting = [0u8; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    greeting
+}
+
+async fn write_frame(stream: &mut BufStream<TcpStream>, flags: u8, body: &[u8]) -> io::Result<()> {
+    match u8::try_from(body.len()) {
+        Ok(len) => stream.write_all(&[flags, len]).await?,
+        Err(_) => {
+            stream.write_all(&[flags | LONG]).await?;
+            stream.write_all(&(body.len() as u64).to_be_bytes()).await?;
+        }
+    }
+    stream.write_all(body).await
+}
+
+async fn read_frame(stream: &mut BufStream<TcpStream>) -> io::Result<(u8, Vec<u8>)> {
+    let flags = stream.read_u8().await?;
+    let len = match flags & LONG {
+        0 => stream.read_u8().await? as u64,
+        _ => stream.read_u64().await?,
+    };
+    // Notifications are tiny, a huge frame means the stream is out of sync
+    if len > 1 << 20 {
+        return Err(protocol_error("frame too large"));
+    }
+    let mut body = vec![0; len as usize];
+    stream.read_exact(&mut body).await?;
+    Ok((flags, body))
+}
+
+/// Reads the frames of the next message, skipping commands.
+async fn read_message(stream: &mut BufStream<TcpStream>) -> io::Result<Vec<Vec<u8>>> {
+    let mut frames = Vec::new();
+    loop {
+        let (flags, body) = read_frame(stream).await?;
+        if flags & COMMAND != 0 {
+            continue;
+        }
+        frames.push(body);
+        if flags & MORE == 0 {
+            return Ok(frames);
+        }
+    }
+}
+
+fn protocol_error(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::MockZmqPublisher;
+
+    #[tokio::test]
+    async fn wakes_on_hashblock_notifications_only() {
+        let publisher = MockZmqPublisher::start().await;
+        let new_blocks = Arc::new(Notify::new());
+        tokio::spawn(watch_blocks(&publisher.url, new_blocks.clone()).unwrap());
+
+        assert_eq!(publisher.subscription().await, b"hashblock");
+        publisher.publish(&[b"hashtx", &[7; 32], &0u32.to_le_bytes()]).await;
+        let woken = tokio::time::timeout(Duration::from_millis(200), new_blocks.notified()).await;
+        assert!(woken.is_err(), "woken by a hashtx notification");
+
+        publisher.publish(&[b"hashblock", &[9; 32], &1u32.to_le_bytes()]).await;
+        tokio::time::timeout(Duration::from_secs(5), new_blocks.notified()).await.expect("no wakeup");
+    }
+
+    #[test]
+    fn rejects_urls_other_than_tcp_with_a_port() {
+        let notify = Arc::new(Notify::new());
+        assert!(watch_blocks("tcp://127.0.0.1:28332", notify.clone()).is_ok());
+        for url in ["ipc:///tmp/bitcoind.sock", "tcp://127.0.0.1", "127.0.0.1:28332"] {
+            assert!(matches!(watch_blocks(url, notify.clone()), Err(IndexerError::Config(_))), "{}", url);
+        }
+    }
+}